@@ -0,0 +1,215 @@
+use crate::*;
+use crate::layout::update_sgr_state;
+
+/// Bidirectional class of a character, after a simplified UAX #9 without explicit embeddings.
+#[derive(Clone, Copy, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+enum Class {
+    Left,
+    Right,
+    Number,
+    Neutral,
+}
+
+/// Letters of scripts written from right to left.
+pub fn is_rtl(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{0590}'..='\u{08FF}' // hebrew, arabic, syriac, thaana, nko, samaritan, mandaic
+            | '\u{FB1D}'..='\u{FDFF}' // hebrew and arabic presentation forms a
+            | '\u{FE70}'..='\u{FEFF}' // arabic presentation forms b
+            | '\u{10800}'..='\u{10FFF}' // historic scripts
+            | '\u{1E800}'..='\u{1EFFF}' // adlam, arabic mathematical symbols
+    ) && !ch.is_numeric()
+}
+
+fn class(ch: char) -> Class {
+    if ch.is_numeric() {
+        Class::Number
+    } else if is_rtl(ch) {
+        Class::Right
+    } else if ch.is_alphabetic() {
+        Class::Left
+    } else {
+        Class::Neutral
+    }
+}
+
+/// Whether the text is right to left by its first letter, if it has any.
+pub fn first_strong_is_rtl(text: &str) -> Option<bool> {
+    text.chars().map(class).find_map(|class| match class {
+        Class::Left => Some(false),
+        Class::Right => Some(true),
+        _ => None,
+    })
+}
+
+/// Embedding level of each character of a line: even is left to right, odd is right to left.
+pub fn levels(chars: &[char], rtl: bool) -> Vec<u8> {
+    let base = if rtl { Class::Right } else { Class::Left };
+    let mut classes: Vec<Class> = chars.iter().map(|ch| class(*ch)).collect();
+    // W7: numbers after left to right text are left to right
+    let mut strong = base;
+    for class in &mut classes {
+        match *class {
+            Class::Left | Class::Right => strong = *class,
+            Class::Number if strong == Class::Left => *class = Class::Left,
+            _ => {},
+        }
+    }
+    // N1, N2: neutrals between text of one direction take it, numbers counting as right to left
+    let direction = |class| if class == Class::Left { Class::Left } else { Class::Right };
+    let mut start = 0;
+    while start < classes.len() {
+        let len = classes[start..].iter().take_while(|class| **class == Class::Neutral).count();
+        if len > 0 {
+            let before = if start == 0 { base } else { direction(classes[start - 1]) };
+            let after = classes.get(start + len).map_or(base, |class| direction(*class));
+            let resolved = if before == after { before } else { base };
+            classes[start..start + len].fill(resolved);
+        }
+        start += len.max(1);
+    }
+    // I1, I2
+    let mut levels: Vec<u8> = classes.into_iter().map(|class| match (class, rtl) {
+        (Class::Left, false) => 0,
+        (Class::Right, _) => 1,
+        _ => 2,
+    }).collect();
+    // L1: trailing whitespace is at the paragraph level
+    let trailing = chars.iter().rev().take_while(|ch| ch.is_whitespace()).count();
+    let len = levels.len();
+    levels[len - trailing..].fill(u8::from(rtl));
+    levels
+}
+
+/// L2: the logical index of the character shown at each position.
+pub fn visual_order(levels: &[u8]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..levels.len()).collect();
+    let highest = levels.iter().copied().max().unwrap_or(0);
+    let lowest_odd = levels.iter().copied().filter(|level| level % 2 == 1).min().unwrap_or(1);
+    for level in (lowest_odd..=highest).rev() {
+        let mut start = 0;
+        while start < levels.len() {
+            let len = order[start..].iter().take_while(|i| levels[**i] >= level).count();
+            order[start..start + len].reverse();
+            start += len.max(1);
+        }
+    }
+    order
+}
+
+/// L4: the glyph of a paired character in right to left text.
+pub fn mirror(ch: char) -> char {
+    match ch {
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '<' => '>',
+        '>' => '<',
+        '\u{AB}' => '\u{BB}',
+        '\u{BB}' => '\u{AB}',
+        '\u{2039}' => '\u{203A}',
+        '\u{203A}' => '\u{2039}',
+        _ => ch,
+    }
+}
+
+/// Reorder a line with escape sequences from logical to visual order, starting in the style
+/// `start_state`. Every character keeps its style. Also gives the logical index of the character
+/// shown at each position.
+pub fn reorder_line(line: &str, start_state: &str, rtl: bool) -> (String, Vec<usize>) {
+    let mut state = start_state.to_string();
+    let mut pending = String::new();
+    let mut escaped = false;
+    let mut cells: Vec<(char, String)> = Vec::new();
+    for ch in line.chars() {
+        if escaped || ch == '\x1B' {
+            escaped = ch == '\x1B' || !ch.is_ascii_alphabetic();
+            pending.push(ch);
+        } else {
+            update_sgr_state(&pending, &mut state);
+            pending.clear();
+            cells.push((ch, state.clone()));
+        }
+    }
+    update_sgr_state(&pending, &mut state);
+    let chars: Vec<char> = cells.iter().map(|(ch, _)| *ch).collect();
+    let levels = levels(&chars, rtl);
+    let order = visual_order(&levels);
+
+    let mut res = String::new();
+    let mut current = start_state.to_string();
+    for i in &order {
+        let (ch, style) = &cells[*i];
+        if *style != current {
+            res += RESET;
+            res += style;
+            current = style.clone();
+        }
+        res.push(if levels[*i] % 2 == 1 { mirror(*ch) } else { *ch });
+    }
+    if current != state {
+        res += RESET;
+        res += &state;
+    }
+    (res, order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visual(line: &str, rtl: bool) -> String {
+        reorder_line(line, "", rtl).0
+    }
+
+    #[test]
+    fn direction_of_the_first_letter() {
+        assert_eq!(first_strong_is_rtl("12 (אבג) abc"), Some(true));
+        assert_eq!(first_strong_is_rtl("abc אבג"), Some(false));
+        assert_eq!(first_strong_is_rtl("12 + 3"), None);
+    }
+
+    #[test]
+    fn levels_of_mixed_text() {
+        let chars: Vec<char> = "abc אבג".chars().collect();
+        assert_eq!(levels(&chars, false), [0, 0, 0, 0, 1, 1, 1]);
+        assert_eq!(levels(&chars, true), [2, 2, 2, 1, 1, 1, 1]);
+        // trailing whitespace is at the paragraph level
+        let chars: Vec<char> = "abc  ".chars().collect();
+        assert_eq!(levels(&chars, true), [2, 2, 2, 1, 1]);
+    }
+
+    #[test]
+    fn reorders_runs() {
+        assert_eq!(visual("אבג abc", true), "abc גבא");
+        assert_eq!(visual("abc אבג def", false), "abc גבא def");
+        // numbers keep their order, also in right to left text
+        assert_eq!(visual("אב 123", true), "123 בא");
+        assert_eq!(visual("abc 123", false), "abc 123");
+    }
+
+    #[test]
+    fn mirrors_brackets() {
+        assert_eq!(visual("(אב)", true), "(בא)");
+        assert_eq!(visual("«אב»", true), "«בא»");
+        assert_eq!(visual("(abc)", true), "(abc)");
+    }
+
+    #[test]
+    fn order_of_positions() {
+        let (_, order) = reorder_line("אב cd", "", true);
+        assert_eq!(order, [3, 4, 2, 1, 0]);
+    }
+
+    #[test]
+    fn characters_keep_their_style() {
+        let (line, _) = reorder_line(&format!("{BOLD}אב{RESET}ג"), "", true);
+        assert_eq!(strip_ansi(&line), "גבא");
+        let bold = line.find(BOLD).expect("bold is kept");
+        assert!(line[..bold].ends_with(RESET) && line[bold..].contains("בא"), "{line:?}");
+    }
+}
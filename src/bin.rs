@@ -2,9 +2,6 @@ use incodoc_to_ansi::*;
 use incodoc_to_ansi::config::*;
 
 use md_to_incodoc::parse_md_to_incodoc;
-use incodoc::output::doc_out;
-use incodoc::reference_doc::REF_DOC;
-use incodoc::parsing::parse;
 
 const INPUT: &str =
 "
@@ -176,43 +173,7 @@ fn main() {
     // doc_out(&doc, &mut output);
     // println!("{output}");
 
-    let cs = term_size::dimensions().unwrap_or((80, 0)).0;
-    let conf = Config {
-        width: cs,
-        nav: NavConfig {
-            link_indent: 3,
-            sub_indent: 3,
-            pre_description_mns: 0,
-            post_description_ns: 0,
-            pre_link_mns: 0,
-        },
-        section: SectionConfig {
-            paragraph_indent: 2,
-            section_indent: 2,
-            pre_item_mns: 0,
-        },
-        headed_section: HeadedSectionConfig {
-            pre_heading_mns: 1,
-            post_heading_ns: 0,
-        },
-        blockquote: BlockquoteConfig {
-            pre_quote_mns: 0,
-        },
-        code_block: CodeBlockConfig {
-            indent: 0,
-            pre_code_block_mns: 0,
-        },
-        list: ListConfig {
-            pre_item_mns: 0,
-        },
-        table: TableConfig {
-            pre_table_mns: 0,
-        },
-        text: TextConfig {
-            swallow_whitespace: true,
-            whitespace_swallowers: ".,;:)}]".to_string(),
-        },
-    };
+    let conf = Config::readable();
     println!("{}", doc_to_ansi_string(&doc, &conf));
 }
 
@@ -0,0 +1,166 @@
+use crate::*;
+use crate::source_map::*;
+
+/// Check or uncheck the item of a checked list at the given path.
+/// Returns whether the item is checked now, or `None` when the path is not such an item.
+pub fn toggle_checked(doc: &mut Doc, item: &[usize]) -> Option<bool> {
+    let (index, list) = item.split_last()?;
+    let Some(NodeMut::Item(ParagraphItem::List(list))) = node_at_path_mut(doc, list) else {
+        return None;
+    };
+    if list.ltype != ListType::Checked {
+        return None;
+    }
+    let par = list.items.get_mut(*index)?;
+    if par.tags.remove("checked") {
+        Some(false)
+    } else {
+        par.tags.insert("checked".to_string());
+        Some(true)
+    }
+}
+
+/// Path of the checked list item that is rendered on the given output row.
+pub fn checklist_item_at<'a>(doc: &Doc, map: &'a SourceMap, row: usize) -> Option<&'a NodePath> {
+    map.spans.iter()
+        .filter(|span| span.kind == NodeKind::ListItem && span.start.0 <= row && row <= span.end.0)
+        .filter(|span| matches!(
+            node_at_path(doc, &span.path[..span.path.len() - 1]),
+            Some(Node::Item(ParagraphItem::List(list))) if list.ltype == ListType::Checked
+        ))
+        .max_by_key(|span| span.path.len())
+        .map(|span| &span.path)
+}
+
+/// Render the checked list at the given path again, after one of its items changed.
+/// The rendered rows of the list in `lines` and its spans in `map` are replaced.
+/// Returns false when the list can't be replaced in place, because it was not recorded or takes up
+/// a different number of rows now; then the whole document should be rendered again.
+pub fn rerender_checklist(
+    doc: &Doc,
+    conf: &Config,
+    state: &RenderState,
+    list: &[usize],
+    lines: &mut [String],
+    map: &mut SourceMap,
+) -> bool {
+    let Some(Node::Item(ParagraphItem::List(node))) = node_at_path(doc, list) else {
+        return false;
+    };
+    let Some(checklist) = map.checklists.iter().find(|checklist| checklist.path == list) else {
+        return false;
+    };
+    let mut c = Context {
+        ps: checklist.ps,
+        fg_mod: checklist.fg_mod.clone(),
+        bg_mod: checklist.bg_mod.clone(),
+        list_depth: checklist.list_depth,
+        indentation: checklist.indentation,
+        indented: checklist.indented,
+        width: checklist.width,
+        col: checklist.col,
+        row: checklist.row,
+        path: checklist.path.clone(),
+        source_map: Some(SourceMap::default()),
+        state: state.clone(),
+        footnotes: number_footnotes(doc, conf),
+        ..Default::default()
+    };
+    let mut output = String::new();
+    list_to_ansi(node, conf, &mut c, &mut output);
+    let new = c.source_map.unwrap_or_default();
+    let first_row = new.spans.iter().map(|span| span.start.0).min();
+    if first_row != Some(checklist.first_row)
+        || c.row + 1 != checklist.first_row + checklist.rows.len()
+        || checklist.rows.iter().any(|(row, _)| *row >= lines.len())
+    {
+        return false;
+    }
+
+    let rendered: Vec<&str> = output.split('\n').collect();
+    // colours and effects carried over from the rows before
+    let mut sgr = String::new();
+    for (i, text) in rendered.iter().enumerate() {
+        let Some(&(row, col)) = (checklist.row + i).checked_sub(checklist.first_row)
+            .and_then(|i| checklist.rows.get(i))
+        else {
+            layout::update_sgr_state(text, &mut sgr);
+            continue;
+        };
+        // blank rows may have been left out at the top of a column, so they have no place
+        if visible_len(text) > 0 {
+            let text = sgr.clone() + text;
+            lines[row] = layout::splice_line(&lines[row], col, checklist.width, &text);
+        }
+        layout::update_sgr_state(text, &mut sgr);
+    }
+    let place = |(row, col): Position| {
+        let (out_row, offset) = checklist.rows[row - checklist.first_row];
+        (out_row, offset + col)
+    };
+    let spans: Vec<Span> = new.spans.into_iter()
+        .map(|span| Span { start: place(span.start), end: place(span.end), ..span })
+        .collect();
+    map.spans.retain(|span| !span.path.starts_with(list) || span.path == list);
+    map.spans.extend(spans);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{ Format, parse_input };
+
+    const MD: &str = "# Tasks\n\nSome text before the list that goes on for a while.\n\n\
+        - [ ] first task\n- [x] second task\n- [ ] third task\n\nSome text after the list.";
+
+    /// Check the first task, render its list again in place and compare with rendering it all.
+    fn rerendered_in_place(conf: &Config) {
+        let mut doc = parse_input(MD, Format::Markdown).expect("markdown always parses");
+        let (output, mut map) = doc_to_ansi_string_with_source_map(&doc, conf);
+        let mut lines: Vec<String> = output.split('\n').map(String::from).collect();
+        let row = lines.iter().position(|line| strip_ansi(line).contains("first task")).unwrap();
+        let item = checklist_item_at(&doc, &map, row).expect("a task is on the row").clone();
+        assert_eq!(toggle_checked(&mut doc, &item), Some(true));
+
+        let list = &item[..item.len() - 1];
+        let state = RenderState::default();
+        assert!(rerender_checklist(&doc, conf, &state, list, &mut lines, &mut map));
+        let (output, mut expected) = doc_to_ansi_string_with_source_map(&doc, conf);
+        let plain: Vec<String> = lines.iter().map(|line| strip_ansi(line)).collect();
+        let expected_plain: Vec<String> = output.split('\n').map(strip_ansi).collect();
+        assert_eq!(plain, expected_plain);
+        map.spans.sort();
+        expected.spans.sort();
+        assert_eq!(map.spans, expected.spans);
+    }
+
+    #[test]
+    fn plain() {
+        rerendered_in_place(&Config { width: 40, ..Config::compact() });
+    }
+
+    #[test]
+    fn measured() {
+        let mut conf = Config { width: 60, ..Config::compact() };
+        conf.max_line_width = 30;
+        rerendered_in_place(&conf);
+    }
+
+    #[test]
+    fn columns() {
+        let mut conf = Config { width: 60, ..Config::compact() };
+        conf.columns.max_count = 2;
+        conf.columns.width = 25;
+        conf.columns.span_headings = true;
+        rerendered_in_place(&conf);
+    }
+
+    #[test]
+    fn not_a_checklist() {
+        let mut doc = parse_input("- first\n- second", Format::Markdown).unwrap();
+        let (_, map) = doc_to_ansi_string_with_source_map(&doc, &Config::compact());
+        assert_eq!(checklist_item_at(&doc, &map, 0), None);
+        assert_eq!(toggle_checked(&mut doc, &[0, 0, 0]), None);
+    }
+}
@@ -0,0 +1,568 @@
+use crate::*;
+use crate::config::{ TextAlign, TextDirection };
+use crate::hyphenation::{ SOFT_HYPHEN, hyphenation_points };
+use crate::layout::update_sgr_state;
+
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// Width of a context that text is rendered in to be composed later: lines are never broken.
+pub const UNBOUNDED: usize = usize::MAX / 2;
+
+/// Text between spaces, with the escape sequences in and right after it.
+#[derive(Clone, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+struct Word {
+    text: String,
+    width: usize,
+    /// column in the uncomposed line of each character from the source
+    cols: Vec<usize>,
+    /// columns each character takes up
+    widths: Vec<usize>,
+    /// soft hyphens: after how many characters the word may be broken
+    breaks: Vec<usize>,
+}
+
+impl Word {
+    /// How many characters from the start of the word fit in `cols` columns.
+    fn chars_within(&self, cols: usize) -> usize {
+        let mut used = 0;
+        self.widths.iter().take_while(|width| {
+            used += *width;
+            used <= cols
+        }).count()
+    }
+}
+
+/// Whether paragraphs are composed a line at a time rather than streamed.
+pub fn composes(conf: &Config) -> bool {
+    conf.text.word_wrap || conf.text.align != TextAlign::Left || conf.text.hyphenation.enabled
+}
+
+/// Whether the item is text, which is composed a line at a time.
+pub fn is_inline(item: &ParagraphItem) -> bool {
+    matches!(
+        item,
+        ParagraphItem::Text(_) | ParagraphItem::MText(_) | ParagraphItem::Em(_)
+            | ParagraphItem::Link(_)
+    )
+}
+
+/// Whether the paragraph is written from right to left, by `conf.text.direction` or else by its
+/// first letter outside inline code.
+pub fn is_rtl_paragraph(par: &Paragraph, conf: &Config) -> bool {
+    match conf.text.direction {
+        TextDirection::Ltr => false,
+        TextDirection::Rtl => true,
+        TextDirection::Auto => par.items.iter()
+            .filter(|item| !matches!(
+                item, ParagraphItem::MText(mtext) if mtext.tags.contains("code")
+            ))
+            .find_map(|item| bidi::first_strong_is_rtl(item_text_start(item)))
+            .unwrap_or(false),
+    }
+}
+
+/// Render the paragraph, composing the text between its lists, tables and code blocks.
+pub fn composed_paragraph_to_ansi(
+    par: &Paragraph, conf: &Config, c: &mut Context, output: &mut String
+) {
+    let rtl = is_rtl_paragraph(par, conf);
+    let mut start = 0;
+    while start < par.items.len() {
+        let inline = is_inline(&par.items[start]);
+        let len = par.items[start..].iter().take_while(|item| is_inline(item) == inline).count();
+        if inline {
+            composed_items_to_ansi(par, start..start + len, rtl, conf, c, output);
+        } else {
+            paragraph_items_to_ansi(par, start..start + len, conf, c, output);
+        }
+        start += len;
+    }
+}
+
+/// Render text items without breaking lines, then break the lines between words and align them
+/// according to `conf.text.align`. Lines of right to left text are reordered, and their alignment
+/// and indentation are mirrored.
+fn composed_items_to_ansi(
+    par: &Paragraph,
+    items: Range<usize>,
+    rtl: bool,
+    conf: &Config,
+    c: &mut Context,
+    output: &mut String,
+) {
+    let map = c.source_map.take();
+    let mut tc = c.clone();
+    c.source_map = map;
+    tc.width = UNBOUNDED;
+    tc.row = 0;
+    tc.source_map = c.source_map.as_ref().map(|_| SourceMap::default());
+    let mut uncomposed = String::new();
+    paragraph_items_to_ansi(par, items, conf, &mut tc, &mut uncomposed);
+    let align = match (conf.text.align, rtl) {
+        (TextAlign::Left, true) => TextAlign::Right,
+        (TextAlign::Right, true) => TextAlign::Left,
+        (align, _) => align,
+    };
+    // text in list items stays next to its marker
+    let in_list = c.list_depth > 0;
+    let align = match align {
+        TextAlign::Right | TextAlign::Centre if in_list => TextAlign::Left,
+        align => align,
+    };
+
+    let full = c.width.saturating_sub(c.indentation).max(1);
+    let (mut row, mut col) = c.pos();
+    let mut state = String::new();
+    // position each column of the uncomposed lines ends up at
+    let mut positions: Vec<Vec<Position>> = Vec::new();
+    for (i, line) in uncomposed.split('\n').enumerate() {
+        if i > 0 {
+            *output += "\n";
+            row += 1;
+            col = 0;
+        }
+        let start_col = col;
+        let (mut prefix, mut prefix_width, words) = split_words(line, start_col);
+        // right to left lines all start at the same origin, with the indentation at their end
+        if rtl {
+            prefix.retain(|ch| ch != ' ');
+            prefix_width = 0;
+        }
+        let mut line_positions: Vec<Option<Position>> =
+            vec![None; start_col + visible_len(line) + 1];
+        for (k, pos) in line_positions.iter_mut().enumerate().take(start_col + prefix_width + 1) {
+            *pos = Some((row, k));
+        }
+        *output += &prefix;
+        update_sgr_state(&prefix, &mut state);
+        col += prefix_width;
+
+        let first = c.width.saturating_sub(col + if rtl { c.indentation } else { 0 }).max(1);
+        let lines = break_words(words, first, full, conf);
+        for (k, words) in lines.iter().enumerate() {
+            let avail = if k == 0 { first } else { full };
+            let content = words.iter().map(|word| word.width).sum::<usize>()
+                + words.len().saturating_sub(1);
+            let extra = avail.saturating_sub(content);
+            let last = k + 1 == lines.len();
+            let gaps = words.len().saturating_sub(1);
+            let (pad, spread) = match align {
+                // nothing to align, such as the rest of the row after a list
+                _ if words.is_empty() => (0, 0),
+                TextAlign::Left => (0, 0),
+                TextAlign::Right => (extra, 0),
+                TextAlign::Centre => (extra / 2, 0),
+                // the last line is aligned to where the text starts
+                TextAlign::Justify if (last || gaps == 0) && rtl && !in_list => (extra, 0),
+                TextAlign::Justify if last || gaps == 0 => (0, 0),
+                TextAlign::Justify => (0, extra),
+            };
+            if k > 0 {
+                *output += "\n";
+                *output += RESET;
+                row += 1;
+                col = if rtl { 0 } else { c.indentation };
+                *output += &" ".repeat(col);
+            }
+            *output += &" ".repeat(pad);
+            col += pad;
+            if k > 0 {
+                *output += &state;
+            }
+            let line_state = state.clone();
+            let content_col = col;
+            let mut content = String::new();
+            for (j, word) in words.iter().enumerate() {
+                if j > 0 {
+                    let gap = 1 + spread / gaps + usize::from(j <= spread % gaps);
+                    content += &" ".repeat(gap);
+                    col += gap;
+                }
+                let mut offset = 0;
+                for (original, width) in word.cols.iter().zip(&word.widths) {
+                    line_positions[*original] = Some((row, col + offset));
+                    offset += width;
+                }
+                content += &word.text;
+                update_sgr_state(&word.text, &mut state);
+                col += word.width;
+                if let (Some(original), Some(width)) = (word.cols.last(), word.widths.last()) {
+                    line_positions[original + width] = Some((row, col));
+                }
+            }
+            if rtl {
+                let (visual, order) = bidi::reorder_line(&content, &line_state, true);
+                let widths: Vec<usize> = strip_ansi(&content).chars().map(char_width).collect();
+                // where each character starts, in logical and in visual order
+                let mut logical = Vec::with_capacity(widths.len());
+                let mut covered = Vec::new();
+                for (i, width) in widths.iter().enumerate() {
+                    logical.push(covered.len());
+                    covered.extend(std::iter::repeat_n(i, *width));
+                }
+                let mut visual_start = vec![0; order.len()];
+                let mut offset = 0;
+                for i in &order {
+                    visual_start[*i] = offset;
+                    offset += widths[*i];
+                }
+                for (pos_row, pos_col) in line_positions.iter_mut().flatten() {
+                    if *pos_row == row && (content_col..col).contains(pos_col) {
+                        let i = covered[*pos_col - content_col];
+                        *pos_col = content_col + visual_start[i] + *pos_col - content_col
+                            - logical[i];
+                    }
+                }
+                content = visual;
+            }
+            *output += &content;
+        }
+        // spaces that were left out end up where the text before them ended
+        let mut previous = (row, start_col);
+        positions.push(line_positions.into_iter().map(|pos| {
+            previous = pos.unwrap_or(previous);
+            previous
+        }).collect());
+    }
+
+    if let Some(target) = &mut c.source_map {
+        let place = |(row, col): Position| {
+            let line = &positions[row.min(positions.len() - 1)];
+            line[col.min(line.len() - 1)]
+        };
+        for mut span in tc.source_map.take().unwrap_or_default().spans {
+            span.start = place(span.start);
+            span.end = place(span.end);
+            // reordered right to left text
+            if span.end < span.start {
+                mem::swap(&mut span.start, &mut span.end);
+            }
+            target.spans.push(span);
+        }
+    }
+    let map = c.source_map.take();
+    let width = c.width;
+    *c = tc;
+    c.source_map = map;
+    c.width = width;
+    c.row = row;
+    c.col = col;
+}
+
+/// Split a line into the leading spaces and escape sequences, their width, and the words after.
+fn split_words(line: &str, start_col: usize) -> (String, usize, Vec<Word>) {
+    let mut prefix = String::new();
+    let mut prefix_width = 0;
+    let mut words = Vec::new();
+    let mut current = Word::default();
+    let mut in_prefix = true;
+    let mut escaped = false;
+    let mut col = start_col;
+    for ch in line.chars() {
+        if escaped || ch == '\x1B' {
+            if escaped && ch.is_ascii_alphabetic() {
+                escaped = false;
+            } else if ch == '\x1B' {
+                escaped = true;
+            }
+            if in_prefix {
+                prefix.push(ch);
+            } else {
+                current.text.push(ch);
+            }
+        } else if ch == SOFT_HYPHEN {
+            if current.width > 0 {
+                current.breaks.push(current.cols.len());
+            }
+        } else if ch == ' ' {
+            if in_prefix {
+                prefix.push(ch);
+                prefix_width += 1;
+            } else if !current.cols.is_empty() {
+                words.push(mem::take(&mut current));
+            }
+            col += 1;
+        } else {
+            in_prefix = false;
+            current.text.push(ch);
+            current.width += char_width(ch);
+            current.cols.push(col);
+            current.widths.push(char_width(ch));
+            col += char_width(ch);
+        }
+    }
+    if !current.cols.is_empty() {
+        words.push(current);
+    } else if let Some(last) = words.last_mut() {
+        last.text += &current.text;
+    } else {
+        prefix += &current.text;
+    }
+    (prefix, prefix_width, words)
+}
+
+/// Fill lines with words: `first` columns for the first line, `full` for the others.
+/// Words that don't fit are broken where the script allows it or hyphenated when possible, words
+/// longer than a line are broken anywhere.
+fn break_words(words: Vec<Word>, first: usize, full: usize, conf: &Config) -> Vec<Vec<Word>> {
+    let mut queue = VecDeque::from(words);
+    let mut lines: Vec<Vec<Word>> = vec![Vec::new()];
+    let mut avail = first;
+    let mut used = 0;
+    while let Some(word) = queue.pop_front() {
+        let current = lines.last_mut().expect("there is always a line");
+        let room = if current.is_empty() { avail } else { avail.saturating_sub(used + 1) };
+        if word.width <= room {
+            used = avail - room + word.width;
+            current.push(word);
+        } else if let Some((head, tail)) = break_word(&word, room) {
+            current.push(head);
+            lines.push(Vec::new());
+            used = 0;
+            avail = full;
+            queue.push_front(tail);
+        } else if let Some((head, tail)) = hyphenate(&word, room, conf) {
+            current.push(head);
+            lines.push(Vec::new());
+            used = 0;
+            avail = full;
+            queue.push_front(tail);
+        } else if current.is_empty() {
+            let (head, tail) = split_word(&word, word.chars_within(avail).max(1));
+            used = head.width;
+            current.push(head);
+            queue.push_front(tail);
+        } else {
+            lines.push(Vec::new());
+            used = 0;
+            avail = full;
+            queue.push_front(word);
+        }
+    }
+    lines
+}
+
+/// Break the word at the last line break opportunity that fits.
+fn break_word(word: &Word, room: usize) -> Option<(Word, Word)> {
+    let fits = word.chars_within(room);
+    let at = line_break::break_points(&strip_ansi(&word.text)).into_iter()
+        .filter(|at| *at <= fits)
+        .max()?;
+    Some(split_word(word, at))
+}
+
+/// Break the word at the last hyphenation point that leaves room for the hyphen.
+fn hyphenate(word: &Word, room: usize, conf: &Config) -> Option<(Word, Word)> {
+    let hyphenation = &conf.text.hyphenation;
+    if !hyphenation.enabled {
+        return None;
+    }
+    let visible: String = strip_ansi(&word.text);
+    let mut points = hyphenation_points(&visible, hyphenation);
+    points.extend(&word.breaks);
+    // room for the hyphen after it
+    let fits = word.chars_within(room.saturating_sub(1));
+    let at = points.into_iter().filter(|at| *at > 0 && *at <= fits && *at < word.cols.len()).max()?;
+    let (mut head, tail) = split_word(word, at);
+    // right after the last character, so in its style
+    head.text.push('-');
+    head.width += 1;
+    Some((head, tail))
+}
+
+/// Split a word after `at` visible characters.
+fn split_word(word: &Word, at: usize) -> (Word, Word) {
+    let mut head = Word::default();
+    let mut tail = Word::default();
+    let mut escaped = false;
+    let mut count = 0;
+    for ch in word.text.chars() {
+        let part = if count < at { &mut head } else { &mut tail };
+        if escaped || ch == '\x1B' {
+            escaped = ch == '\x1B' || !ch.is_ascii_alphabetic();
+        } else {
+            count += 1;
+        }
+        part.text.push(ch);
+    }
+    head.cols = word.cols[..at].to_vec();
+    tail.cols = word.cols[at..].to_vec();
+    head.widths = word.widths[..at].to_vec();
+    tail.widths = word.widths[at..].to_vec();
+    head.width = head.widths.iter().sum();
+    tail.width = tail.widths.iter().sum();
+    head.breaks = word.breaks.iter().copied().filter(|point| *point < at).collect();
+    tail.breaks = word.breaks.iter().filter(|point| **point > at).map(|point| point - at).collect();
+    (head, tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Style;
+    use crate::input::{ Format, parse_input };
+
+    const HEBREW: &str = "שלום עולם זה טקסט בעברית שנמשך על פני כמה שורות ארוכות מאוד";
+
+    fn render(text: &str, align: TextAlign, indent: usize) -> Vec<String> {
+        let doc = parse_input(text, Format::Markdown).expect("markdown always parses");
+        let mut conf = Config { width: 24, ..Default::default() };
+        conf.text.align = align;
+        conf.styles.insert("paragraph".to_string(), Style { indent, ..Default::default() });
+        strip_ansi(&doc_to_ansi_string(&doc, &conf)).lines().map(String::from).collect()
+    }
+
+    /// First and last column with something in it.
+    fn edges(line: &str) -> (usize, usize) {
+        let start = line.chars().take_while(|ch| *ch == ' ').count();
+        (start, line.trim_end().chars().count())
+    }
+
+    #[test]
+    fn rtl_lines_share_their_edges() {
+        for indent in [0, 4] {
+            let lines = render(HEBREW, TextAlign::Left, indent);
+            assert!(lines.len() > 2);
+            assert!(lines.iter().all(|line| edges(line).1 == 24 - indent), "{lines:?}");
+
+            let lines = render(HEBREW, TextAlign::Justify, indent);
+            let (last, full) = lines.split_last().expect("there are lines");
+            assert!(full.iter().all(|line| edges(line) == (0, 24 - indent)), "{lines:?}");
+            assert_eq!(edges(last).1, 24 - indent, "{lines:?}");
+            assert!(edges(last).0 > 0, "{lines:?}");
+        }
+    }
+
+    #[test]
+    fn rtl_lines_are_reordered() {
+        let lines = render("שלום עולם", TextAlign::Left, 0);
+        assert_eq!(lines, [format!("{:>24}", "םלוע םולש")]);
+    }
+
+    const LATIN: &str = "The quick brown fox jumps over the lazy dog and keeps on running";
+
+    #[test]
+    fn splits_words() {
+        let (prefix, width, words) = split_words("  ab  c\u{AD}d", 0);
+        assert_eq!((prefix.as_str(), width), ("  ", 2));
+        let words: Vec<(&str, &[usize], &[usize])> = words.iter()
+            .map(|word| (word.text.as_str(), word.cols.as_slice(), word.breaks.as_slice()))
+            .collect();
+        assert_eq!(words, [("ab", &[2, 3][..], &[][..]), ("cd", &[6, 7][..], &[1][..])]);
+    }
+
+    #[test]
+    fn breaks_between_words() {
+        let (_, _, words) = split_words(LATIN, 0);
+        let lines = break_words(words, 10, 20, &Config::default());
+        let lines: Vec<String> = lines.iter()
+            .map(|line| line.iter().map(|word| word.text.as_str()).collect::<Vec<_>>().join(" "))
+            .collect();
+        assert_eq!(lines, [
+            "The quick", "brown fox jumps over", "the lazy dog and", "keeps on running",
+        ]);
+    }
+
+    #[test]
+    fn breaks_words_longer_than_a_line() {
+        let (_, _, words) = split_words("abcdefghij", 0);
+        let lines = break_words(words, 4, 4, &Config::default());
+        let lines: Vec<&str> = lines.iter().flatten().map(|word| word.text.as_str()).collect();
+        assert_eq!(lines, ["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn hyphenates_and_uses_soft_hyphens() {
+        let mut conf = Config::default();
+        conf.text.hyphenation.enabled = true;
+        let (_, _, words) = split_words("an extensive list", 0);
+        let lines = break_words(words, 8, 8, &conf);
+        let texts: Vec<Vec<&str>> = lines.iter()
+            .map(|line| line.iter().map(|word| word.text.as_str()).collect())
+            .collect();
+        assert_eq!(texts, [vec!["an", "ex-"], vec!["tensive"], vec!["list"]]);
+
+        // soft hyphens are used whether or not words are hyphenated by the patterns
+        conf.text.hyphenation.language = "xx".to_string();
+        let (_, _, words) = split_words("an exten\u{AD}sive list", 0);
+        let lines = break_words(words, 9, 9, &conf);
+        assert_eq!(lines[0][1].text, "exten-");
+        assert_eq!(lines[1][0].text, "sive");
+    }
+
+    fn composed(align: TextAlign) -> Vec<String> {
+        let doc = parse_input(LATIN, Format::Markdown).expect("markdown always parses");
+        let mut conf = Config { width: 24, ..Config::compact() };
+        conf.text.align = align;
+        conf.text.word_wrap = true;
+        strip_ansi(&doc_to_ansi_string(&doc, &conf)).lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn justifies_all_lines_but_the_last() {
+        let lines = composed(TextAlign::Justify);
+        let (last, full) = lines.split_last().expect("there are lines");
+        assert!(full.len() > 1);
+        assert!(full.iter().all(|line| edges(line) == (0, 24)), "{lines:?}");
+        assert!(!last.contains("  "), "{lines:?}");
+        let words: Vec<&str> = lines.iter().flat_map(|line| line.split_whitespace()).collect();
+        assert_eq!(words.join(" "), LATIN);
+    }
+
+    #[test]
+    fn aligns_lines() {
+        let left = composed(TextAlign::Left);
+        assert!(left.iter().all(|line| edges(line).0 == 0 && edges(line).1 <= 24), "{left:?}");
+        let right = composed(TextAlign::Right);
+        assert!(right.iter().all(|line| edges(line).1 == 24), "{right:?}");
+        let centre = composed(TextAlign::Centre);
+        for (line, plain) in centre.iter().zip(&left) {
+            let (start, end) = edges(line);
+            assert_eq!(end - start, plain.trim().chars().count(), "{centre:?}");
+            assert!(start.abs_diff(24 - end) <= 1, "{centre:?}");
+        }
+    }
+
+    /// A paragraph with a list of the items followed by the text.
+    fn list_then_text(items: &[&str], text: &str, align: TextAlign) -> Vec<String> {
+        let list = List {
+            ltype: ListType::Identical,
+            items: items.iter().map(|item| Paragraph {
+                items: vec![ParagraphItem::Text(item.to_string())],
+                ..Default::default()
+            }).collect(),
+            ..Default::default()
+        };
+        let par = Paragraph {
+            items: vec![ParagraphItem::List(list), ParagraphItem::Text(text.to_string())],
+            ..Default::default()
+        };
+        let doc = Doc { items: vec![DocItem::Paragraph(par)], ..Default::default() };
+        let mut conf = Config { width: 30, ..Config::compact() };
+        conf.text.align = align;
+        strip_ansi(&doc_to_ansi_string(&doc, &conf)).lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn rows_after_lists_are_not_padded() {
+        for align in [TextAlign::Right, TextAlign::Centre, TextAlign::Justify] {
+            let lines = list_then_text(&["a", "b"], "after", align);
+            assert!(lines.iter().all(|line| line.trim_end() == line), "{align:?}: {lines:?}");
+            assert!(lines.iter().any(|line| line.trim() == "after"), "{align:?}: {lines:?}");
+        }
+        let lines = list_then_text(&["a", "b"], "שלום עולם", TextAlign::Left);
+        assert!(lines.iter().all(|line| line.trim_end() == line), "{lines:?}");
+        assert_eq!(lines.last().map(|line| line.trim_start()), Some("םלוע םולש"), "{lines:?}");
+    }
+
+    #[test]
+    fn list_text_stays_next_to_its_marker() {
+        for align in [TextAlign::Right, TextAlign::Centre, TextAlign::Justify] {
+            let lines = list_then_text(&["a", "b c"], "", align);
+            assert_eq!(lines[..2], ["• a", "• b c"], "{align:?}");
+        }
+        let lines = list_then_text(&["שלום", "עולם"], "", TextAlign::Left);
+        assert_eq!(lines[..2], ["• םולש", "• םלוע"]);
+    }
+}
@@ -1,7 +1,25 @@
+#[cfg(feature = "serde")]
+use serde::{ Deserialize, Serialize };
 
-#[derive(Clone, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+use std::collections::{ BTreeMap, BTreeSet };
+
+use terminal_size::{ Width, terminal_size };
+
+#[cfg(feature = "serde")]
+use std::{ env, fs, path::{ Path, PathBuf } };
+
+#[derive(Clone, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
 pub struct Config {
     pub width: usize,
+    /// columns between tab stops in text and code, counted from the indentation; in text, tabs
+    /// are collapsed like other whitespace when 0
+    pub tab_width: usize,
+    /// widest the body is laid out at, the rest of the width is margin; no limit when 0
+    pub max_line_width: usize,
+    pub body_align: BodyAlign,
+    /// margin left of a left aligned body
+    pub body_margin: usize,
     pub nav: NavConfig,
     pub section: SectionConfig,
     pub headed_section: HeadedSectionConfig,
@@ -10,18 +28,271 @@ pub struct Config {
     pub list: ListConfig,
     pub table: TableConfig,
     pub text: TextConfig,
+    pub footnote: FootnoteConfig,
+    pub columns: ColumnsConfig,
+    /// styles by selector: an element and tags, such as "section.warning" or "text.kbd"; the
+    /// elements are section, paragraph, text, emphasis and link, and ".kbd" is any of them
+    pub styles: BTreeMap<String, Style>,
+    pub header: HeaderConfig,
+    pub filter: FilterConfig,
+    pub spoiler: SpoilerConfig,
+    pub colour_level: ColourLevel,
+}
+
+impl Default for Config {
+    /// The readable preset at the width of the terminal.
+    fn default() -> Self {
+        Self::readable()
+    }
+}
+
+impl Config {
+    /// Little indentation and no empty lines other than before headings.
+    pub fn compact() -> Self {
+        Self {
+            width: terminal_width(),
+            tab_width: 2,
+            max_line_width: 0,
+            body_align: BodyAlign::Left,
+            body_margin: 0,
+            nav: NavConfig {
+                style: NavStyle::Plain,
+                link_indent: 2,
+                sub_indent: 2,
+                pre_description_mns: 0,
+                post_description_ns: 0,
+                pre_link_mns: 0,
+                sidebar: Sidebar::None,
+                sidebar_width: 24,
+                sidebar_gutter: 2,
+                sidebar_min_width: 40,
+            },
+            section: SectionConfig {
+                paragraph_indent: 1,
+                section_indent: 1,
+                pre_item_mns: 0,
+            },
+            headed_section: HeadedSectionConfig {
+                pre_heading_mns: 0,
+                post_heading_ns: 0,
+            },
+            blockquote: BlockquoteConfig {
+                pre_quote_mns: 0,
+            },
+            code_block: CodeBlockConfig {
+                indent: 0,
+                pre_code_block_mns: 0,
+                theme: "ansi".to_string(),
+            },
+            list: ListConfig {
+                pre_item_mns: 0,
+                ..Default::default()
+            },
+            table: TableConfig {
+                pre_table_mns: 0,
+            },
+            text: TextConfig::default(),
+            footnote: FootnoteConfig::default(),
+            columns: ColumnsConfig {
+                max_count: 1,
+                width: 72,
+                gutter: 3,
+                span_headings: true,
+            },
+            styles: BTreeMap::new(),
+            header: HeaderConfig::default(),
+            filter: FilterConfig::default(),
+            spoiler: SpoilerConfig::default(),
+            colour_level: ColourLevel::default(),
+        }
+    }
+
+    /// Indented sections with an empty line before each heading.
+    pub fn readable() -> Self {
+        Self {
+            width: terminal_width(),
+            tab_width: 4,
+            max_line_width: 0,
+            body_align: BodyAlign::Left,
+            body_margin: 0,
+            nav: NavConfig {
+                style: NavStyle::Tree,
+                link_indent: 3,
+                sub_indent: 3,
+                pre_description_mns: 0,
+                post_description_ns: 0,
+                pre_link_mns: 0,
+                sidebar: Sidebar::None,
+                sidebar_width: 30,
+                sidebar_gutter: 3,
+                sidebar_min_width: 60,
+            },
+            section: SectionConfig {
+                paragraph_indent: 2,
+                section_indent: 2,
+                pre_item_mns: 0,
+            },
+            headed_section: HeadedSectionConfig {
+                pre_heading_mns: 1,
+                post_heading_ns: 0,
+            },
+            blockquote: BlockquoteConfig {
+                pre_quote_mns: 0,
+            },
+            code_block: CodeBlockConfig {
+                indent: 0,
+                pre_code_block_mns: 0,
+                theme: "ansi".to_string(),
+            },
+            list: ListConfig {
+                pre_item_mns: 0,
+                ..Default::default()
+            },
+            table: TableConfig {
+                pre_table_mns: 0,
+            },
+            text: TextConfig::default(),
+            footnote: FootnoteConfig::default(),
+            columns: ColumnsConfig {
+                max_count: 1,
+                width: 80,
+                gutter: 4,
+                span_headings: true,
+            },
+            styles: BTreeMap::new(),
+            header: HeaderConfig::default(),
+            filter: FilterConfig::default(),
+            spoiler: SpoilerConfig::default(),
+            colour_level: ColourLevel::default(),
+        }
+    }
+
+    /// Deep indentation and empty lines between all blocks.
+    pub fn spacious() -> Self {
+        Self {
+            width: terminal_width(),
+            tab_width: 4,
+            max_line_width: 100,
+            body_align: BodyAlign::Centre,
+            body_margin: 0,
+            nav: NavConfig {
+                style: NavStyle::Tree,
+                link_indent: 4,
+                sub_indent: 4,
+                pre_description_mns: 1,
+                post_description_ns: 0,
+                pre_link_mns: 0,
+                sidebar: Sidebar::None,
+                sidebar_width: 34,
+                sidebar_gutter: 4,
+                sidebar_min_width: 70,
+            },
+            section: SectionConfig {
+                paragraph_indent: 4,
+                section_indent: 4,
+                pre_item_mns: 1,
+            },
+            headed_section: HeadedSectionConfig {
+                pre_heading_mns: 2,
+                post_heading_ns: 1,
+            },
+            blockquote: BlockquoteConfig {
+                pre_quote_mns: 1,
+            },
+            code_block: CodeBlockConfig {
+                indent: 2,
+                pre_code_block_mns: 1,
+                theme: "ansi".to_string(),
+            },
+            list: ListConfig {
+                pre_item_mns: 0,
+                ..Default::default()
+            },
+            table: TableConfig {
+                pre_table_mns: 1,
+            },
+            text: TextConfig::default(),
+            footnote: FootnoteConfig::default(),
+            columns: ColumnsConfig {
+                max_count: 1,
+                width: 88,
+                gutter: 6,
+                span_headings: true,
+            },
+            styles: BTreeMap::new(),
+            header: HeaderConfig::default(),
+            filter: FilterConfig::default(),
+            spoiler: SpoilerConfig::default(),
+            colour_level: ColourLevel::default(),
+        }
+    }
+
+    /// Preset by name: `compact`, `readable` or `spacious`.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "compact" => Some(Self::compact()),
+            "readable" => Some(Self::readable()),
+            "spacious" => Some(Self::spacious()),
+            _ => None,
+        }
+    }
+}
+
+/// Width of the terminal standard output is connected to.
+/// Falls back to `$COLUMNS` and then to 80 when it is not a terminal.
+pub fn terminal_width() -> usize {
+    if let Some((Width(cols), _)) = terminal_size() && cols > 0 {
+        return cols as usize;
+    }
+    std::env::var("COLUMNS").ok()
+        .and_then(|cols| cols.parse().ok())
+        .filter(|cols| *cols > 0)
+        .unwrap_or(80)
 }
 
 #[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
 pub struct NavConfig {
+    pub style: NavStyle,
     pub link_indent: usize,
     pub sub_indent: usize,
     pub pre_description_mns: usize,
     pub post_description_ns: usize,
     pub pre_link_mns: usize,
+    /// column to put navigation in, when the width allows it
+    pub sidebar: Sidebar,
+    pub sidebar_width: usize,
+    pub sidebar_gutter: usize,
+    /// least width left for the content next to the sidebar; narrower falls back to inline
+    pub sidebar_min_width: usize,
 }
 
+/// How navigation is laid out.
 #[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum NavStyle {
+    /// links and sub-navigation indented under the description
+    #[default]
+    Plain,
+    /// like a file tree, with box-drawing connectors
+    Tree,
+    /// each navigation on one line, links separated by arrows
+    Breadcrumb,
+}
+
+/// Side of the document navigation is put on.
+#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Sidebar {
+    /// inline, where it is in the document
+    #[default]
+    None,
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
 pub struct SectionConfig {
     pub paragraph_indent: usize,
     pub section_indent: usize,
@@ -29,34 +300,590 @@ pub struct SectionConfig {
 }
 
 #[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
 pub struct HeadedSectionConfig {
     pub pre_heading_mns: usize,
     pub post_heading_ns: usize,
 }
 
 #[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
 pub struct BlockquoteConfig {
     pub pre_quote_mns: usize,
 }
 
-#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Clone, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
 pub struct CodeBlockConfig {
     pub indent: usize,
     pub pre_code_block_mns: usize,
+    /// bat theme the code is highlighted with; "ansi" uses the colours of the terminal
+    pub theme: String,
+}
+
+impl Default for CodeBlockConfig {
+    fn default() -> Self {
+        Self {
+            indent: 0,
+            pre_code_block_mns: 0,
+            theme: "ansi".to_string(),
+        }
+    }
 }
 
+/// Colours the terminal can show.
 #[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum ColourLevel {
+    /// the 16 colours of the terminal; code is highlighted with the "ansi" theme
+    Basic,
+    /// 256 colours; the colours of code themes are approximated
+    Ansi256,
+    #[default]
+    TrueColour,
+}
+
+#[derive(Clone, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
 pub struct ListConfig {
     pub pre_item_mns: usize,
+    /// minimum newlines between the items of loose lists: lists with items of multiple blocks
+    pub loose_pre_item_mns: usize,
+    /// extra indentation of item content after the marker
+    pub hanging_indent: usize,
+    /// alignment of markers of different widths, like multi-digit numbers
+    pub marker_align: Align,
+    /// markers of identical lists by nesting depth, repeating when nested deeper
+    pub bullets: Vec<String>,
+    /// numbering of distinct lists by nesting depth, repeating when nested deeper
+    pub numbering: Vec<Numbering>,
+    /// number of the first item of distinct lists
+    pub start: usize,
+    /// marker of checked items of checked lists
+    pub checked: String,
+    /// marker of unchecked items of checked lists
+    pub unchecked: String,
+    /// colours of the markers; its border, indentation and prefix are not used
+    pub marker_style: Style,
+}
+
+impl Default for ListConfig {
+    fn default() -> Self {
+        Self {
+            pre_item_mns: 0,
+            loose_pre_item_mns: 1,
+            hanging_indent: 0,
+            marker_align: Align::Right,
+            bullets: vec!["•".to_string(), "◦".to_string(), "▪".to_string()],
+            numbering: vec![Numbering::Decimal, Numbering::LowerAlpha, Numbering::LowerRoman],
+            start: 1,
+            checked: "☑".to_string(),
+            unchecked: "☐".to_string(),
+            marker_style: Style::default(),
+        }
+    }
+}
+
+/// Where the body goes when the width is more than `max_line_width`.
+#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum BodyAlign {
+    /// after `body_margin`
+    #[default]
+    Left,
+    Centre,
 }
 
 #[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Align {
+    #[default]
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Numbering {
+    /// 1, 2, 3
+    #[default]
+    Decimal,
+    /// a, b, c, ..., z, aa, ab
+    LowerAlpha,
+    /// A, B, C, ..., Z, AA, AB
+    UpperAlpha,
+    /// i, ii, iii, iv
+    LowerRoman,
+    /// I, II, III, IV
+    UpperRoman,
+}
+
+impl Numbering {
+    /// Format a number in this numbering. Numbers that can't be expressed fall back to decimal.
+    pub fn format(&self, number: usize) -> String {
+        match self {
+            _ if number == 0 => "0".to_string(),
+            Self::Decimal => number.to_string(),
+            Self::LowerAlpha => alpha(number),
+            Self::UpperAlpha => alpha(number).to_uppercase(),
+            Self::LowerRoman if number < 4000 => roman(number),
+            Self::UpperRoman if number < 4000 => roman(number).to_uppercase(),
+            Self::LowerRoman | Self::UpperRoman => number.to_string(),
+        }
+    }
+}
+
+fn alpha(mut number: usize) -> String {
+    let mut res = Vec::new();
+    while number > 0 {
+        number -= 1;
+        res.push((b'a' + (number % 26) as u8) as char);
+        number /= 26;
+    }
+    res.iter().rev().collect()
+}
+
+fn roman(mut number: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "m"), (900, "cm"), (500, "d"), (400, "cd"), (100, "c"), (90, "xc"),
+        (50, "l"), (40, "xl"), (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i"),
+    ];
+    let mut res = String::new();
+    for (value, numeral) in NUMERALS {
+        while number >= value {
+            res += numeral;
+            number -= value;
+        }
+    }
+    res
+}
+
+#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
 pub struct TableConfig {
     pub pre_table_mns: usize,
 }
 
-#[derive(Clone, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Clone, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
 pub struct TextConfig {
-    pub swallow_whitespace: bool,
-    pub whitespace_swallowers: String,
+    /// language of the text, for example "fr", selects the punctuation rules
+    pub language: String,
+    /// spacing around punctuation by language; "default" is for languages not in it
+    pub punctuation: BTreeMap<String, Vec<PunctuationRule>>,
+    /// break lines between words and where UAX #14 allows it, rather than anywhere
+    pub word_wrap: bool,
+    /// alignment of paragraph text; other than left, lines are broken as with `word_wrap`
+    pub align: TextAlign,
+    /// direction of paragraphs; right to left paragraphs are broken as with `word_wrap`, and
+    /// their alignment and indentation are mirrored
+    pub direction: TextDirection,
+    pub hyphenation: HyphenationConfig,
+    /// paragraphs and sections with any of these tags keep all whitespace and newlines
+    pub pre_tags: BTreeSet<String>,
+    /// what happens to lines of preformatted text that are too long
+    pub pre_overflow: PreOverflow,
+    /// curly quotes, dashes from `--` and `---`, `...` as an ellipsis and `(c)` as a copyright
+    /// sign; never in code
+    pub smart_typography: bool,
+}
+
+/// Breaking words at the end of a line, which also breaks lines as with `word_wrap`.
+#[derive(Clone, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
+pub struct HyphenationConfig {
+    pub enabled: bool,
+    /// language of the TeX hyphenation patterns, for example "en" or "de-ch";
+    /// soft hyphens are used in any language
+    pub language: String,
+    /// shortest word that is hyphenated
+    pub min_word: usize,
+    /// least characters before the hyphen
+    pub min_before: usize,
+    /// least characters on the next line
+    pub min_after: usize,
+}
+
+impl Default for HyphenationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            language: "en".to_string(),
+            min_word: 5,
+            min_before: 2,
+            min_after: 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Right,
+    Centre,
+    /// spread out over the whole width, except the last line
+    Justify,
+}
+
+#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum PreOverflow {
+    /// continue on the next line
+    #[default]
+    Wrap,
+    /// cut off at the width
+    Truncate,
+}
+
+#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum TextDirection {
+    /// by the first letter of the paragraph
+    #[default]
+    Auto,
+    Ltr,
+    Rtl,
+}
+
+impl Default for TextConfig {
+    fn default() -> Self {
+        let rule = |chars: &str, spacing| PunctuationRule { chars: chars.to_string(), spacing };
+        let punctuation = BTreeMap::from([
+            ("default".to_string(), vec![
+                rule(".,;:!?)]}\u{201D}", Spacing::NoSpaceBefore),
+                rule("([{\u{201C}", Spacing::NoSpaceAfter),
+                rule("\"", Spacing::Quote),
+            ]),
+            ("de".to_string(), vec![
+                rule(".,;:!?)]}\u{201C}", Spacing::NoSpaceBefore),
+                rule("([{\u{201E}", Spacing::NoSpaceAfter),
+                rule("\"", Spacing::Quote),
+            ]),
+            ("fr".to_string(), vec![
+                rule(".,)]}\u{201D}", Spacing::NoSpaceBefore),
+                rule(";:!?\u{BB}", Spacing::NbspBefore),
+                rule("\u{AB}", Spacing::NbspAfter),
+                rule("([{\u{201C}", Spacing::NoSpaceAfter),
+                rule("\"", Spacing::Quote),
+            ]),
+        ]);
+        Self {
+            language: "en".to_string(),
+            punctuation,
+            word_wrap: false,
+            align: TextAlign::Left,
+            direction: TextDirection::Auto,
+            hyphenation: HyphenationConfig::default(),
+            pre_tags: BTreeSet::from(["pre".to_string()]),
+            pre_overflow: PreOverflow::Wrap,
+            smart_typography: false,
+        }
+    }
+}
+
+/// Spacing of the given punctuation characters.
+#[derive(Clone, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
+pub struct PunctuationRule {
+    pub chars: String,
+    pub spacing: Spacing,
+}
+
+#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Spacing {
+    /// delete the space before, like closing punctuation
+    #[default]
+    NoSpaceBefore,
+    /// delete the space after, like opening brackets
+    NoSpaceAfter,
+    /// put a non-breaking space before, instead of a space if there is one
+    NbspBefore,
+    /// put a non-breaking space after, instead of a space if there is one
+    NbspAfter,
+    /// opening after a space or at the start of text, else closing
+    Quote,
+}
+
+impl Config {
+    /// Check the settings that can not be checked by their type.
+    pub fn validate(&self) -> Result<(), String> {
+        let hyphenation = &self.text.hyphenation;
+        if hyphenation.enabled && crate::hyphenation::language(&hyphenation.language).is_none() {
+            return Err(format!("no hyphenation patterns for language {:?}", hyphenation.language));
+        }
+        let theme = &self.code_block.theme;
+        if !bat::PrettyPrinter::new().themes().any(|name| name == theme) {
+            return Err(format!("unknown code theme {theme:?}"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Config {
+    /// Parse a TOML configuration. Only the keys that are present override `base`.
+    pub fn from_toml_str(text: &str, base: &Config) -> Result<Config, String> {
+        let overrides: toml::Table = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut merged = toml::Table::try_from(base).map_err(|e| e.to_string())?;
+        merge_toml(&mut merged, overrides);
+        let conf: Config = merged.try_into().map_err(|e: toml::de::Error| e.to_string())?;
+        conf.validate()?;
+        Ok(conf)
+    }
+
+    /// Load a TOML configuration file. Only the keys that are present override `base`.
+    pub fn load(path: &Path, base: &Config) -> Result<Config, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+        Self::from_toml_str(&text, base).map_err(|e| format!("in {}: {e}", path.display()))
+    }
+
+    /// `$XDG_CONFIG_HOME/incodoc-to-ansi/config.toml`, or `~/.config/incodoc-to-ansi/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        config_path(|key| env::var_os(key))
+    }
+
+    /// Load the configuration file at the default path on top of `base`.
+    /// When there is no such file `base` is returned as is.
+    pub fn load_default(base: &Config) -> Result<Config, String> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load(&path, base),
+            _ => Ok(base.clone()),
+        }
+    }
+}
+
+/// The default configuration path with environment variables looked up by `var`.
+#[cfg(feature = "serde")]
+fn config_path(var: impl Fn(&str) -> Option<std::ffi::OsString>) -> Option<PathBuf> {
+    let dir = var("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(dir.join("incodoc-to-ansi").join("config.toml"))
+}
+
+#[cfg(feature = "serde")]
+fn merge_toml(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => {
+                merge_toml(base, overrides);
+            },
+            (_, value) => {
+                base.insert(key, value);
+            },
+        }
+    }
+}
+
+#[derive(Clone, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
+pub struct FootnoteConfig {
+    pub style: FootnoteStyle,
+    pub placement: NotePlacement,
+    /// heading of the block of notes, none when empty
+    pub heading: String,
+    pub pre_notes_mns: usize,
+}
+
+impl Default for FootnoteConfig {
+    fn default() -> Self {
+        Self {
+            style: FootnoteStyle::Superscript,
+            placement: NotePlacement::Document,
+            heading: "Notes".to_string(),
+            pre_notes_mns: 1,
+        }
+    }
+}
+
+/// Newspaper-like columns the document flows through when the width allows more than one.
+#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
+pub struct ColumnsConfig {
+    /// most columns to use, one or none means no columns
+    pub max_count: usize,
+    /// least width of a column
+    pub width: usize,
+    pub gutter: usize,
+    /// put headings across all columns, the text in between flows through the columns
+    pub span_headings: bool,
+}
+
+/// Header at the top with props of the document, such as its title, author and date.
+#[derive(Clone, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
+pub struct HeaderConfig {
+    /// props to show, in this order; no header when none of them are there
+    pub props: Vec<String>,
+    /// set the title of the terminal window to the title prop
+    pub window_title: bool,
+}
+
+/// Leaving out sections, paragraphs and the elements in paragraphs by their tags.
+#[derive(Clone, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
+pub struct FilterConfig {
+    /// elements with any of these tags are kept, even when they also have an excluded tag
+    pub include: BTreeSet<String>,
+    /// elements with any of these tags are left out
+    pub exclude: BTreeSet<String>,
+    /// shown in place of what is left out; nothing when empty
+    pub placeholder: String,
+}
+
+/// How strongly deemphasised text, a spoiler, is shown.
+#[derive(Clone, Copy, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
+pub struct SpoilerConfig {
+    pub treatment: SpoilerTreatment,
+    /// shown in place of each character of masked text
+    pub mask: char,
+    /// show all spoilers as plain text
+    pub reveal: bool,
+}
+
+impl Default for SpoilerConfig {
+    fn default() -> Self {
+        Self {
+            treatment: SpoilerTreatment::Mask,
+            mask: '\u{2588}',
+            reveal: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum SpoilerTreatment {
+    /// the hidden attribute, which not all terminals support
+    Hidden,
+    /// each character replaced by the mask
+    #[default]
+    Mask,
+    /// faint text
+    Dim,
+    /// masked until focused in the viewer, then faint
+    Focus,
+}
+
+/// Style of elements with certain tags. Borders and indentation only apply to sections and
+/// paragraphs.
+#[derive(Clone, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default, deny_unknown_fields))]
+pub struct Style {
+    pub fg: Option<Colour>,
+    pub bg: Option<Colour>,
+    /// a box around it, as around blockquotes
+    pub border: bool,
+    pub indent: usize,
+    /// put in front of its text
+    pub prefix: String,
+}
+
+#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum Colour {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    /// of the terminal
+    #[default]
+    Default,
+}
+
+/// How footnote references are shown.
+#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum FootnoteStyle {
+    /// ¹²
+    #[default]
+    Superscript,
+    /// [12]
+    Brackets,
+}
+
+/// Where footnote definitions are collected.
+#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "kebab-case"))]
+pub enum NotePlacement {
+    /// at the end of the document
+    #[default]
+    Document,
+    /// at the end of each top level section
+    Section,
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_toml_overrides_only_its_keys() {
+        let base = Config::compact();
+        let text = "width = 60\n[text]\nword_wrap = true\n[footnote]\nstyle = \"brackets\"\n";
+        let conf = Config::from_toml_str(text, &base).expect("valid config");
+        assert_eq!(conf.width, 60);
+        assert!(conf.text.word_wrap);
+        assert_eq!(conf.footnote.style, FootnoteStyle::Brackets);
+        // everything else, also next to the overridden keys, comes from the base
+        assert_eq!(conf.text.align, base.text.align);
+        assert_eq!(conf.text.hyphenation, base.text.hyphenation);
+        assert_eq!(conf.footnote.placement, base.footnote.placement);
+        assert_eq!(conf.list, base.list);
+        assert_eq!(Config::from_toml_str("", &base), Ok(base));
+    }
+
+    #[test]
+    fn unknown_keys_are_errors() {
+        let err = Config::from_toml_str("[text]\nwordwrap = true\n", &Config::default())
+            .expect_err("unknown key");
+        assert!(err.contains("unknown field `wordwrap`"), "{err}");
+        assert!(Config::from_toml_str("width = \"wide\"\n", &Config::default()).is_err());
+    }
+
+    #[test]
+    fn invalid_values_are_errors() {
+        let err = Config::from_toml_str(
+            "[text.hyphenation]\nenabled = true\nlanguage = \"xx\"\n",
+            &Config::default(),
+        ).expect_err("unknown language");
+        assert_eq!(err, "no hyphenation patterns for language \"xx\"");
+        let err = Config::from_toml_str("[code_block]\ntheme = \"nope\"\n", &Config::default())
+            .expect_err("unknown theme");
+        assert_eq!(err, "unknown code theme \"nope\"");
+        let conf = Config::from_toml_str("[code_block]\ntheme = \"Nord\"\n", &Config::default());
+        assert_eq!(conf.map(|conf| conf.code_block.theme), Ok("Nord".to_string()));
+    }
+
+    #[test]
+    fn default_paths() {
+        let path = |xdg: Option<&str>, home: Option<&str>| config_path(|key| match key {
+            "XDG_CONFIG_HOME" => xdg.map(Into::into),
+            "HOME" => home.map(Into::into),
+            _ => None,
+        });
+        assert_eq!(
+            path(Some("/xdg"), Some("/home/me")),
+            Some(PathBuf::from("/xdg/incodoc-to-ansi/config.toml")),
+        );
+        assert_eq!(
+            path(Some(""), Some("/home/me")),
+            Some(PathBuf::from("/home/me/.config/incodoc-to-ansi/config.toml")),
+        );
+        assert_eq!(
+            path(None, Some("/home/me")),
+            Some(PathBuf::from("/home/me/.config/incodoc-to-ansi/config.toml")),
+        );
+        assert_eq!(path(None, None), None);
+    }
 }
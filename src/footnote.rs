@@ -0,0 +1,311 @@
+use crate::*;
+use crate::config::{ FootnoteStyle, NotePlacement };
+use crate::source_map::NodePath;
+
+use std::collections::BTreeMap;
+
+pub fn is_footnote_ref(link: &Link) -> bool {
+    link.tags.contains("footnote-ref")
+}
+
+pub fn is_footnote_def(section: &Section) -> bool {
+    section.tags.contains("footnote-def")
+}
+
+/// Identifier that references and definitions share: the url of the reference.
+pub fn footnote_def_id(section: &Section) -> Option<&str> {
+    match section.props.get("id") {
+        Some(PropVal::String(id)) => Some(id),
+        _ => None,
+    }
+}
+
+/// Number the footnotes of the whole document: in order of the first reference to them, followed
+/// by the definitions that are never referenced. What is filtered out doesn't count.
+pub fn number_footnotes(doc: &Doc, conf: &Config) -> BTreeMap<String, usize> {
+    let mut numbers = BTreeMap::new();
+    let mut add = |id: &str| {
+        let next = numbers.len() + 1;
+        numbers.entry(id.to_string()).or_insert(next);
+    };
+    for_each_paragraph_item(doc, conf, &mut |item| {
+        if let ParagraphItem::Link(link) = item && is_footnote_ref(link) {
+            add(&link.url);
+        }
+    });
+    for (_, def) in footnote_defs(doc, conf) {
+        if let Some(id) = footnote_def_id(def) {
+            add(id);
+        }
+    }
+    numbers
+}
+
+/// Call `f` for each paragraph item of the document that is not filtered out, in document order.
+fn for_each_paragraph_item(doc: &Doc, conf: &Config, f: &mut impl FnMut(&ParagraphItem)) {
+    fn par(p: &Paragraph, conf: &Config, f: &mut impl FnMut(&ParagraphItem)) {
+        if is_excluded(&p.tags, conf) {
+            return;
+        }
+        for item in &p.items {
+            if item_tags(item).is_some_and(|tags| is_excluded(tags, conf)) {
+                continue;
+            }
+            f(item);
+            match item {
+                ParagraphItem::List(list) => list.items.iter().for_each(|p| par(p, conf, f)),
+                ParagraphItem::Table(table) => {
+                    table.rows.iter().flat_map(|row| &row.items).for_each(|p| par(p, conf, f));
+                },
+                _ => { },
+            }
+        }
+    }
+    fn section(s: &Section, conf: &Config, f: &mut impl FnMut(&ParagraphItem)) {
+        if is_excluded(&s.tags, conf) {
+            return;
+        }
+        for item in &s.items {
+            match item {
+                SectionItem::Paragraph(p) => par(p, conf, f),
+                SectionItem::Section(s) => section(s, conf, f),
+            }
+        }
+    }
+    for item in &doc.items {
+        match item {
+            DocItem::Paragraph(p) => par(p, conf, f),
+            DocItem::Section(s) => section(s, conf, f),
+            DocItem::Nav(_) => { },
+        }
+    }
+}
+
+/// Footnote definitions of the document with their paths, in document order.
+/// Definitions that are filtered out, or in a section that is, are left out.
+pub fn footnote_defs<'a>(doc: &'a Doc, conf: &Config) -> Vec<(NodePath, &'a Section)> {
+    let mut defs = Vec::new();
+    for (i, item) in doc.items.iter().enumerate() {
+        if let DocItem::Section(section) = item {
+            collect_defs(section, vec![i], conf, &mut defs);
+        }
+    }
+    defs
+}
+
+/// Footnote definitions in a section (including itself) with their paths, in document order.
+/// Definitions that are filtered out are left out.
+pub fn section_footnote_defs<'a>(section: &'a Section, path: &[usize], conf: &Config)
+    -> Vec<(NodePath, &'a Section)>
+{
+    let mut defs = Vec::new();
+    collect_defs(section, path.to_vec(), conf, &mut defs);
+    defs
+}
+
+fn collect_defs<'a>(
+    section: &'a Section, path: NodePath, conf: &Config, defs: &mut Vec<(NodePath, &'a Section)>
+) {
+    if is_excluded(&section.tags, conf) {
+        return;
+    }
+    if is_footnote_def(section) {
+        defs.push((path, section));
+        return;
+    }
+    for (i, item) in section.items.iter().enumerate() {
+        if let SectionItem::Section(sub) = item {
+            let mut path = path.clone();
+            path.push(i);
+            collect_defs(sub, path, conf, defs);
+        }
+    }
+}
+
+/// Marker of a footnote reference: its number.
+pub fn footnote_marker(number: usize, style: FootnoteStyle) -> String {
+    match style {
+        FootnoteStyle::Brackets => format!("[{number}]"),
+        FootnoteStyle::Superscript => {
+            const SUPERSCRIPTS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+            number.to_string().chars()
+                .filter_map(|digit| digit.to_digit(10))
+                .map(|digit| SUPERSCRIPTS[digit as usize])
+                .collect()
+        },
+    }
+}
+
+pub fn footnote_ref_to_ansi(link: &Link, conf: &Config, c: &mut Context, output: &mut String) {
+    let Some(number) = c.footnotes.get(&link.url).copied() else {
+        link_to_ansi(link, conf, c, output);
+        return;
+    };
+    // the marker sticks to the word it follows
+    if c.ps == ParStatus::Whitespace && output.ends_with(' ') {
+        output.pop();
+        c.col -= 1;
+    }
+    if c.col >= c.width {
+        newline(c, output);
+    }
+    if matches!(c.ps, ParStatus::Newline(_) | ParStatus::New(_)) {
+        indent(0, c, output);
+    }
+    c.push_fg_mod(MAGENTA, output);
+    append(&footnote_marker(number, conf.footnote.style), c, output);
+    c.pop_fg_mod(output);
+    c.ps = ParStatus::Char;
+}
+
+/// Render the "Notes" block with the given footnote definitions, ordered by their number.
+pub fn notes_to_ansi(
+    defs: Vec<(NodePath, &Section)>, conf: &Config, c: &mut Context, output: &mut String
+) {
+    let mut notes: Vec<(usize, NodePath, &Section)> = defs.into_iter()
+        .filter_map(|(path, def)| {
+            let number = *c.footnotes.get(footnote_def_id(def)?)?;
+            Some((number, path, def))
+        })
+        .collect();
+    if notes.is_empty() {
+        return;
+    }
+    notes.sort_by_key(|(number, _, _)| *number);
+    let width = notes.iter().map(|(number, _, _)| number.to_string().len()).max().unwrap_or(1);
+    let iwidth = width + 2;
+
+    c.set_ps_new();
+    newlines_minimum(conf.footnote.pre_notes_mns + 1, false, c, output);
+    if !conf.footnote.heading.is_empty() {
+        c.push_fg_mod(BOLD, output);
+        text_to_ansi(&conf.footnote.heading, conf, c, output);
+        c.pop_fg_mod(output);
+    }
+    let path = mem::take(&mut c.path);
+    for (number, def_path, def) in notes {
+        newlines_minimum(1, false, c, output);
+        c.path = def_path;
+        let start = c.text_pos();
+        indent(0, c, output);
+        append(&format!("{number:>width$}. "), c, output);
+        c.set_ps_new();
+        c.push_indent(iwidth, iwidth);
+        for (i, item) in def.items.iter().enumerate() {
+            if i > 0 {
+                newlines_minimum(1, false, c, output);
+            }
+            c.path.push(i);
+            match item {
+                SectionItem::Paragraph(par) => {
+                    c.set_ps_new();
+                    paragraph_to_ansi(par, conf, c, output);
+                },
+                SectionItem::Section(section) => section_to_ansi(section, conf, c, output),
+            }
+            c.path.pop();
+        }
+        c.pop_indent();
+        c.ps = ParStatus::Element;
+        c.record_span(source_map::NodeKind::Section, start);
+    }
+    c.path = path;
+}
+
+/// Whether the notes of this section are to be rendered at its end.
+pub fn notes_at_section_end(conf: &Config, c: &Context) -> bool {
+    conf.footnote.placement == NotePlacement::Section && c.path.len() == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{ Format, parse_input };
+
+    const MD: &str = "First[^a] and second[^b].\n\nThird[^c].\n\n\
+        [^a]: note a\n[^b]: note b\n[^c]: note c\n";
+
+    fn doc() -> Doc {
+        parse_input(MD, Format::Markdown).expect("markdown always parses")
+    }
+
+    /// Leaves out whatever is tagged "draft".
+    fn conf() -> Config {
+        let mut conf = Config { width: 40, ..Config::compact() };
+        conf.filter.exclude.insert("draft".to_string());
+        conf
+    }
+
+    fn tag(tags: &mut Tags) {
+        tags.insert("draft".to_string());
+    }
+
+    fn numbers(doc: &Doc) -> Vec<(&'static str, usize)> {
+        let numbers = number_footnotes(doc, &conf());
+        ["a", "b", "c"].into_iter()
+            .filter_map(|id| Some((id, *numbers.get(&format!("#footnote-{id}"))?)))
+            .collect()
+    }
+
+    fn paragraph(doc: &mut Doc, i: usize) -> &mut Paragraph {
+        match &mut doc.items[i] {
+            DocItem::Paragraph(par) => par,
+            _ => panic!("item {i} is a paragraph"),
+        }
+    }
+
+    fn def(doc: &mut Doc, i: usize) -> &mut Section {
+        match &mut doc.items[i] {
+            DocItem::Section(section) => section,
+            _ => panic!("item {i} is a section"),
+        }
+    }
+
+    #[test]
+    fn numbered_by_first_reference() {
+        assert_eq!(numbers(&doc()), [("a", 1), ("b", 2), ("c", 3)]);
+        assert_eq!(footnote_defs(&doc(), &conf()).len(), 3);
+    }
+
+    #[test]
+    fn excluded_references_do_not_count() {
+        let mut doc = doc();
+        tag(&mut paragraph(&mut doc, 0).tags);
+        // the definitions are still there, so they come after the referenced one
+        assert_eq!(numbers(&doc), [("a", 2), ("b", 3), ("c", 1)]);
+
+        let mut doc = self::doc();
+        if let ParagraphItem::Link(link) = &mut paragraph(&mut doc, 0).items[3] {
+            tag(&mut link.tags);
+        }
+        assert_eq!(numbers(&doc), [("a", 1), ("b", 3), ("c", 2)]);
+    }
+
+    #[test]
+    fn excluded_definitions_are_left_out() {
+        let mut doc = doc();
+        tag(&mut def(&mut doc, 3).tags);
+        let ids: Vec<&str> = footnote_defs(&doc, &conf()).into_iter()
+            .filter_map(|(_, def)| footnote_def_id(def))
+            .collect();
+        assert_eq!(ids, ["#footnote-a", "#footnote-c"]);
+        assert_eq!(numbers(&doc), [("a", 1), ("b", 2), ("c", 3)]);
+    }
+
+    #[test]
+    fn notes_of_excluded_content() {
+        let mut doc = doc();
+        tag(&mut paragraph(&mut doc, 0).tags);
+        tag(&mut def(&mut doc, 2).tags);
+        tag(&mut def(&mut doc, 3).tags);
+        let text = strip_ansi(&doc_to_ansi_string(&doc, &conf()));
+        assert!(text.contains("Third¹"), "{text}");
+        assert!(text.contains("1. note c") && !text.contains("note a"), "{text}");
+    }
+
+    #[test]
+    fn markers() {
+        assert_eq!(footnote_marker(12, FootnoteStyle::Brackets), "[12]");
+        assert_eq!(footnote_marker(12, FootnoteStyle::Superscript), "¹²");
+    }
+}
@@ -0,0 +1,112 @@
+use crate::*;
+
+/// Text of a prop value, unless it couldn't be parsed.
+fn prop_text(value: &PropVal) -> Option<String> {
+    match value {
+        PropVal::String(text) | PropVal::Text(text) => Some(text.clone()),
+        PropVal::Int(int) => Some(int.to_string()),
+        PropVal::Date(date) => Some(format!("{:04}-{:02}-{:02}", date.year, date.month, date.day)),
+        PropVal::Error(_) => None,
+    }
+}
+
+/// The selected props of the document in a box: the title in bold, the others with their name.
+pub fn header_to_ansi(doc: &Doc, conf: &Config, c: &mut Context, output: &mut String) {
+    let props: Vec<(&String, String)> = conf.header.props.iter()
+        .filter_map(|key| Some((key, prop_text(doc.props.get(key)?)?)))
+        .collect();
+    if props.is_empty() {
+        return;
+    }
+    let mut temp = String::new();
+    let (row, col) = c.pos();
+    // the box is indented and adds a border and padding on both sides
+    let width = c.width;
+    c.width = width.saturating_sub(c.indentation + 4).max(1);
+    for (i, (key, value)) in props.iter().enumerate() {
+        if i > 0 {
+            newline(c, &mut temp);
+        }
+        if *key == "title" {
+            c.push_fg_mod(BOLD, &mut temp);
+            text_to_ansi(value, conf, c, &mut temp);
+            c.pop_fg_mod(&mut temp);
+        } else {
+            let mut name = key.chars();
+            let name: String = name.next().into_iter().flat_map(char::to_uppercase)
+                .chain(name)
+                .collect();
+            c.push_fg_mod(FAINT, &mut temp);
+            text_to_ansi(&format!("{name}:"), conf, c, &mut temp);
+            c.pop_fg_mod(&mut temp);
+            text_to_ansi(value, conf, c, &mut temp);
+        }
+    }
+    let mut table = term_table::Table::builder()
+        .style(TableStyle::thin())
+        .build();
+    let mut table_row = Row::empty();
+    table_row.add_cell(TableCell::new(temp));
+    table.add_row(table_row);
+    c.width = width;
+    c.row = row;
+    c.col = col;
+    *output += RESET;
+    indent_table(&table.render(), c, output);
+    *output += &c.fg_mod;
+    c.ps = ParStatus::Element;
+}
+
+/// OSC 2 escape sequence that sets the title of the terminal window to that of the document.
+pub fn window_title(doc: &Doc, conf: &Config) -> Option<String> {
+    if !conf.header.window_title {
+        return None;
+    }
+    let title = prop_text(doc.props.get("title")?)?;
+    let title: String = title.chars().filter(|ch| !ch.is_control()).collect();
+    Some(format!("\x1B]2;{title}\x07"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titled(title: &str) -> Doc {
+        let mut doc = Doc::default();
+        doc.props.insert("title".to_string(), PropVal::String(title.to_string()));
+        doc.props.insert("author".to_string(), PropVal::Text("Someone".to_string()));
+        doc
+    }
+
+    fn header(doc: &Doc, width: usize) -> Vec<String> {
+        let mut conf = Config { width, ..Default::default() };
+        conf.header.props = vec!["title".to_string(), "date".to_string(), "author".to_string()];
+        strip_ansi(&doc_to_ansi_string(doc, &conf)).lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn boxes_fit_the_width() {
+        let doc = titled("A title that is quite long and keeps going on and on past the width");
+        let lines = header(&doc, 50);
+        assert_eq!(lines.len(), 5, "{lines:?}");
+        assert!(lines.iter().all(|line| visible_len(line) == 50), "{lines:?}");
+        assert!(lines[1].starts_with("│ A title"), "{lines:?}");
+        assert_eq!(lines[3].trim_end_matches([' ', '│']), "│ Author: Someone");
+    }
+
+    #[test]
+    fn no_box_without_props() {
+        assert!(header(&Doc::default(), 50).is_empty());
+    }
+
+    #[test]
+    fn window_titles() {
+        let mut conf = Config::default();
+        assert_eq!(window_title(&titled("Title"), &conf), None);
+        conf.header.window_title = true;
+        assert_eq!(window_title(&titled("Title"), &conf).as_deref(), Some("\x1B]2;Title\x07"));
+        // control characters could end the sequence early
+        assert_eq!(window_title(&titled("a\x07b\nc"), &conf).as_deref(), Some("\x1B]2;abc\x07"));
+        assert_eq!(window_title(&Doc::default(), &conf), None);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::config::HyphenationConfig;
+
+use hypher::{ Lang, hyphenate_bounded };
+
+/// Invisible unless a word is broken at it.
+pub const SOFT_HYPHEN: char = '\u{AD}';
+
+/// Language with embedded TeX hyphenation patterns, by its code such as "en", "en-gb" or "de_CH".
+pub fn language(code: &str) -> Option<Lang> {
+    let base = code.split(['-', '_']).next().unwrap_or_default().to_ascii_lowercase();
+    let code: [u8; 2] = base.as_bytes().try_into().ok()?;
+    Lang::from_iso(code)
+}
+
+/// Where a word may be hyphenated according to the patterns of the configured language.
+/// Only runs of letters are hyphenated; the points count all characters of `word`.
+pub fn hyphenation_points(word: &str, conf: &HyphenationConfig) -> Vec<usize> {
+    let Some(lang) = language(&conf.language) else {
+        return Vec::new();
+    };
+    let chars: Vec<char> = word.chars().collect();
+    let mut res = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let len = chars[start..].iter().take_while(|ch| ch.is_alphabetic()).count();
+        if len >= conf.min_word.max(1) {
+            let run: String = chars[start..start + len].iter().collect();
+            let mut at = start;
+            let syllables = hyphenate_bounded(&run, lang, conf.min_before, conf.min_after);
+            for syllable in syllables {
+                at += syllable.chars().count();
+                res.push(at);
+            }
+            // the end of the run is no hyphenation point
+            res.pop();
+        }
+        start += len.max(1);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hyphenated(word: &str, language: &str) -> String {
+        let conf = HyphenationConfig {
+            enabled: true,
+            language: language.to_string(),
+            min_word: 5,
+            min_before: 2,
+            min_after: 2,
+        };
+        let mut res = String::new();
+        let points = hyphenation_points(word, &conf);
+        for (i, ch) in word.chars().enumerate() {
+            if points.contains(&i) {
+                res.push('-');
+            }
+            res.push(ch);
+        }
+        res
+    }
+
+    #[test]
+    fn english() {
+        assert_eq!(hyphenated("hyphenation", "en"), "hy-phen-ation");
+        assert_eq!(hyphenated("extensive", "en-us"), "ex-ten-sive");
+        assert_eq!(hyphenated("typesetting", "en"), "type-set-ting");
+        assert_eq!(hyphenated("Computer", "en"), "Com-put-er");
+    }
+
+    #[test]
+    fn other_languages() {
+        assert_eq!(hyphenated("Silbentrennung", "de"), "Sil-ben-tren-nung");
+        assert_eq!(hyphenated("Silbentrennung", "de_CH"), "Sil-ben-tren-nung");
+    }
+
+    #[test]
+    fn bounds_and_runs() {
+        // too short, and the hyphen would leave too few letters on either side
+        assert_eq!(hyphenated("word", "en"), "word");
+        assert_eq!(hyphenated("hyphenation-extensive", "en"), "hy-phen-ation-ex-ten-sive");
+        assert_eq!(hyphenated("12345", "en"), "12345");
+    }
+
+    #[test]
+    fn unknown_languages() {
+        assert!(language("xx").is_none());
+        assert!(language("english").is_none());
+        assert!(language("nl-BE").is_some());
+        assert_eq!(hyphenated("hyphenation", "xx"), "hyphenation");
+    }
+
+    #[test]
+    fn unknown_languages_are_rejected() {
+        let mut conf = crate::config::Config::default();
+        conf.text.hyphenation.language = "xx".to_string();
+        assert!(conf.validate().is_ok());
+        conf.text.hyphenation.enabled = true;
+        assert!(conf.validate().is_err());
+        conf.text.hyphenation.language = "en-gb".to_string();
+        assert!(conf.validate().is_ok());
+    }
+}
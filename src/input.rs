@@ -0,0 +1,39 @@
+use incodoc::Doc;
+use incodoc::parsing::parse;
+use md_to_incodoc::parse_md_to_incodoc;
+
+/// Format of a document before it is parsed into an incodoc.
+#[derive(Clone, Copy, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Format {
+    #[default]
+    Incodoc,
+    Markdown,
+}
+
+impl Format {
+    /// Guess the format from a file name: markdown extensions are markdown, the rest incodoc.
+    pub fn from_path(path: &str) -> Self {
+        let lower = path.to_lowercase();
+        if [".md", ".markdown", ".mdown", ".mkd"].iter().any(|ext| lower.ends_with(ext)) {
+            Self::Markdown
+        } else {
+            Self::Incodoc
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "incodoc" | "ic" => Some(Self::Incodoc),
+            "markdown" | "md" => Some(Self::Markdown),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a document in the given format.
+pub fn parse_input(input: &str, format: Format) -> Result<Doc, String> {
+    match format {
+        Format::Incodoc => parse(input),
+        Format::Markdown => Ok(parse_md_to_incodoc(input)),
+    }
+}
@@ -0,0 +1,548 @@
+use crate::*;
+use crate::config::{ BodyAlign, Sidebar };
+
+use std::ops::Range;
+
+/// Put independently rendered columns next to each other, line by line.
+/// Each column is given with the width it is padded or cut to. Colours and effects that are
+/// active at the end of a line of a column are carried over to the next line of that column, but
+/// never leak into the gutter or the next column.
+pub fn compose_columns(columns: &[(&str, usize)], gutter: usize) -> String {
+    let lines: Vec<Vec<&str>> = columns.iter()
+        .map(|(text, _)| if text.is_empty() { Vec::new() } else { text.split('\n').collect() })
+        .collect();
+    let rows = lines.iter().map(|lines| lines.len()).max().unwrap_or(0);
+    let mut states = vec![String::new(); columns.len()];
+    let mut res = String::new();
+    for row in 0..rows {
+        if row > 0 {
+            res.push('\n');
+        }
+        let texts: Vec<&str> = lines.iter()
+            .map(|lines| lines.get(row).copied().unwrap_or_default())
+            .collect();
+        // nothing is padded after the last column that has something on this row
+        let last = texts.iter().rposition(|text| visible_len(text) > 0).unwrap_or(0);
+        for (i, (text, (_, width))) in texts.iter().zip(columns).enumerate() {
+            if i <= last {
+                if i > 0 {
+                    res.extend(std::iter::repeat_n(' ', gutter));
+                }
+                let mut fitted = fit_line(text, *width);
+                if i == last {
+                    fitted.truncate(fitted.trim_end_matches(' ').len());
+                }
+                res += &states[i];
+                res += &fitted;
+                res += RESET;
+            }
+            update_sgr_state(text, &mut states[i]);
+        }
+    }
+    res
+}
+
+/// Cut or pad the line to exactly `width` columns, keeping all escape sequences.
+/// A wide character that would stick out is left out.
+pub fn fit_line(line: &str, width: usize) -> String {
+    let mut res = String::with_capacity(line.len() + width);
+    let mut visible = 0;
+    let mut cut = false;
+    let mut escaped = false;
+    for ch in line.chars() {
+        if escaped {
+            res.push(ch);
+            if ch.is_ascii_alphabetic() {
+                escaped = false;
+            }
+        } else if ch == '\x1B' {
+            res.push(ch);
+            escaped = true;
+        } else if !cut && visible + char_width(ch) <= width {
+            res.push(ch);
+            visible += char_width(ch);
+        } else {
+            cut = true;
+        }
+    }
+    res.extend(std::iter::repeat_n(' ', width - visible));
+    res
+}
+
+/// Split the line after `col` columns. Escape sequences right after the split go with the rest.
+pub fn split_line(line: &str, col: usize) -> (&str, &str) {
+    let mut visible = 0;
+    let mut end = 0;
+    let mut escaped = false;
+    for (i, ch) in line.char_indices() {
+        if escaped {
+            if ch.is_ascii_alphabetic() {
+                escaped = false;
+            }
+        } else if ch == '\x1B' {
+            escaped = true;
+        } else if visible < col {
+            visible += char_width(ch);
+            end = i + ch.len_utf8();
+        } else {
+            break;
+        }
+    }
+    line.split_at(end)
+}
+
+/// Put `text` in the line in place of the `width` columns from `col` on.
+/// What comes after is put back in the colours and effects it had.
+pub fn splice_line(line: &str, col: usize, width: usize, text: &str) -> String {
+    let (before, rest) = split_line(line, col);
+    let (replaced, after) = split_line(rest, width);
+    let mut res = before.to_string();
+    res.extend(std::iter::repeat_n(' ', col.saturating_sub(visible_len(before))));
+    if visible_len(after) == 0 {
+        res += text;
+        return res;
+    }
+    let mut state = String::new();
+    update_sgr_state(before, &mut state);
+    update_sgr_state(replaced, &mut state);
+    res += &fit_line(text, width);
+    res += RESET;
+    res += &state;
+    res += after;
+    res
+}
+
+/// Keep track of the SGR sequences (colours and effects) that are active after the line.
+/// A reset clears the state.
+pub fn update_sgr_state(line: &str, state: &mut String) {
+    let mut rest = line;
+    while let Some(start) = rest.find('\x1B') {
+        rest = &rest[start..];
+        let end = rest.char_indices()
+            .skip(1)
+            .find(|(_, ch)| ch.is_ascii_alphabetic())
+            .map(|(i, ch)| i + ch.len_utf8())
+            .unwrap_or(rest.len());
+        let seq = &rest[..end];
+        if seq.ends_with('m') {
+            let params = seq.trim_start_matches("\x1B[").trim_end_matches('m');
+            if params.trim_start_matches('0').is_empty() {
+                state.clear();
+            } else {
+                *state += seq;
+            }
+        }
+        rest = &rest[end..];
+    }
+}
+
+/// Fresh context for rendering a column of the given width, sharing the document-wide state.
+pub fn column_context(c: &Context, width: usize) -> Context {
+    Context {
+        fg_mod: RESET.to_string(),
+        width,
+        ps: ParStatus::New(1000),
+        path: c.path.clone(),
+        source_map: c.source_map.as_ref().map(|_| SourceMap::default()),
+        state: c.state.clone(),
+        footnotes: c.footnotes.clone(),
+        ..Default::default()
+    }
+}
+
+/// Whether the document is laid out narrower than the width, with a margin.
+pub fn measure_applies(conf: &Config, c: &Context) -> bool {
+    c.path.is_empty() && conf.max_line_width > 0 && c.width > conf.max_line_width
+}
+
+/// Lay the document out at `max_line_width` and put it in the width according to `body_align`.
+pub fn measured_doc_to_ansi(doc: &Doc, conf: &Config, c: &mut Context, output: &mut String) {
+    let width = conf.max_line_width;
+    let margin = match conf.body_align {
+        BodyAlign::Left => conf.body_margin.min(c.width - width),
+        BodyAlign::Centre => (c.width - width) / 2,
+    };
+    let mut mc = column_context(c, width);
+    let mut rendered = String::new();
+    doc_to_ansi(doc, conf, &mut mc, &mut rendered);
+
+    let padding = " ".repeat(margin);
+    let body = rendered.split('\n')
+        .map(|line| if strip_ansi(line).trim().is_empty() {
+            line.to_string()
+        } else {
+            padding.clone() + line
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if let Some(map) = &mut c.source_map {
+        let mut body_map = mc.source_map.unwrap_or_default();
+        body_map.shift(0, c.row, c.col + margin);
+        map.spans.extend(body_map.spans);
+        for mut checklist in body_map.checklists {
+            checklist.shift(c.row, c.col + margin);
+            map.checklists.push(checklist);
+        }
+    }
+    *output += &body;
+    c.advance(&body);
+    c.ps = ParStatus::Element;
+}
+
+/// Whether the navigation of the document is to be rendered in a sidebar.
+pub fn sidebar_fits(doc: &Doc, conf: &Config, c: &Context) -> bool {
+    conf.nav.sidebar != Sidebar::None
+        && c.path.is_empty()
+        && c.width >= conf.nav.sidebar_width + conf.nav.sidebar_gutter + conf.nav.sidebar_min_width
+        && doc.items.iter().any(|item| matches!(item, DocItem::Nav(_)))
+}
+
+/// Render the navigation of the document in a column next to the rest of it.
+pub fn sidebar_doc_to_ansi(doc: &Doc, conf: &Config, c: &mut Context, output: &mut String) {
+    let gutter = conf.nav.sidebar_gutter;
+    let nav_width = conf.nav.sidebar_width;
+    let main_width = c.width - nav_width - gutter;
+
+    let mut nc = column_context(c, nav_width);
+    let mut nav_output = String::new();
+    for (i, item) in doc.items.iter().enumerate() {
+        if let DocItem::Nav(nav) = item {
+            nc.path.push(i);
+            nav_to_ansi(nav, conf, &mut nc, &mut nav_output);
+            nc.path.pop();
+        }
+    }
+    let mut mc = column_context(c, main_width);
+    let mut main_output = String::new();
+    doc_items_to_ansi(doc, conf, false, &mut mc, &mut main_output);
+
+    let (columns, offsets) = match conf.nav.sidebar {
+        Sidebar::Right => (
+            [(main_output.as_str(), main_width), (nav_output.as_str(), nav_width)],
+            [(mc.source_map, 0), (nc.source_map, main_width + gutter)],
+        ),
+        _ => (
+            [(nav_output.as_str(), nav_width), (main_output.as_str(), main_width)],
+            [(nc.source_map, 0), (mc.source_map, nav_width + gutter)],
+        ),
+    };
+    let composed = compose_columns(&columns, gutter);
+    if let Some(map) = &mut c.source_map {
+        for (column_map, cols) in offsets {
+            let mut column_map = column_map.unwrap_or_default();
+            column_map.shift(0, c.row, c.col + cols);
+            map.spans.extend(column_map.spans);
+            for mut checklist in column_map.checklists {
+                checklist.shift(c.row, c.col + cols);
+                map.checklists.push(checklist);
+            }
+        }
+    }
+    *output += &composed;
+    c.advance(&composed);
+    c.ps = ParStatus::Element;
+}
+
+/// Number of columns the document flows through at the width of the context.
+pub fn column_count(conf: &Config, c: &Context) -> usize {
+    let columns = &conf.columns;
+    if !c.path.is_empty() || columns.max_count < 2 {
+        return 1;
+    }
+    ((c.width + columns.gutter) / (columns.width + columns.gutter).max(1)).min(columns.max_count)
+}
+
+/// Render the document at the width of a column and balance its lines over `count` columns.
+/// Headings, when spanning, cut the flow: the text between two headings is balanced on its own.
+pub fn columns_doc_to_ansi(
+    doc: &Doc, conf: &Config, count: usize, c: &mut Context, output: &mut String
+) {
+    let gutter = conf.columns.gutter;
+    let width = (c.width - (count - 1) * gutter) / count;
+    let mut cc = column_context(c, width);
+    cc.source_map = Some(SourceMap::default());
+    let mut rendered = String::new();
+    if sidebar_fits(doc, conf, &cc) {
+        sidebar_doc_to_ansi(doc, conf, &mut cc, &mut rendered);
+    } else {
+        doc_items_to_ansi(doc, conf, true, &mut cc, &mut rendered);
+    }
+    let map = cc.source_map.unwrap_or_default();
+    let lines: Vec<&str> = rendered.split('\n').collect();
+
+    let mut spanning = vec![false; lines.len()];
+    if conf.columns.span_headings {
+        let quotes: Vec<&Span> = map.spans.iter()
+            .filter(|span| span.kind == NodeKind::Blockquote)
+            .collect();
+        for span in map.spans.iter().filter(|span| span.kind == NodeKind::Heading) {
+            let quoted = quotes.iter()
+                .any(|quote| quote.start.0 <= span.start.0 && span.end.0 <= quote.end.0);
+            if !quoted {
+                spanning[span.start.0..=span.end.0.min(lines.len() - 1)].fill(true);
+            }
+        }
+    }
+    // boxes and code are kept in one column
+    let mut breakable = vec![true; lines.len()];
+    for span in &map.spans {
+        if matches!(span.kind, NodeKind::Blockquote | NodeKind::Table | NodeKind::CodeBlock) {
+            let end = (span.end.0 + 1).min(lines.len());
+            breakable[(span.start.0 + 1).min(end)..end].fill(false);
+        }
+    }
+    let blank: Vec<bool> = lines.iter().map(|line| visible_len(line.trim_end()) == 0).collect();
+
+    // where each rendered row ends up: row and column offset
+    let mut places = vec![(0, 0); lines.len()];
+    let mut composed: Vec<String> = Vec::new();
+    // colours and effects active at the start of the row, carried into the next column
+    let mut state = String::new();
+    let mut row = 0;
+    while row < lines.len() {
+        if spanning[row] {
+            places[row] = (composed.len(), 0);
+            composed.push(state.clone() + lines[row]);
+            update_sgr_state(lines[row], &mut state);
+            row += 1;
+            continue;
+        }
+        let end = (row..lines.len()).find(|row| spanning[*row]).unwrap_or(lines.len());
+        let ranges = balance(&breakable[row..end], &blank[row..end], count);
+        let mut chunks = Vec::with_capacity(count);
+        let mut done = 0;
+        for (k, range) in ranges.into_iter().enumerate() {
+            let chunk = &lines[row + range.start..row + range.end];
+            for (i, place) in places[row + range.start..row + range.end].iter_mut().enumerate() {
+                *place = (composed.len() + i, k * (width + gutter));
+            }
+            // blank rows left out at the top of a column still change the state
+            lines[row + done..row + range.start].iter()
+                .for_each(|line| update_sgr_state(line, &mut state));
+            chunks.push(state.clone() + &chunk.join("\n"));
+            chunk.iter().for_each(|line| update_sgr_state(line, &mut state));
+            done = range.end;
+        }
+        let columns: Vec<(&str, usize)> = chunks.iter()
+            .map(|chunk| (chunk.as_str(), width))
+            .collect();
+        composed.extend(compose_columns(&columns, gutter).split('\n').map(String::from));
+        row = end;
+    }
+
+    if let Some(target) = &mut c.source_map {
+        let place = |(row, col): Position| {
+            let (new_row, offset) = places.get(row).copied().unwrap_or_default();
+            (c.row + new_row, c.col + offset + col)
+        };
+        for mut span in map.spans {
+            span.start = place(span.start);
+            span.end = place(span.end);
+            target.spans.push(span);
+        }
+        for mut checklist in map.checklists {
+            checklist.rows.iter_mut().for_each(|pos| *pos = place(*pos));
+            target.checklists.push(checklist);
+        }
+    }
+    let composed = composed.join("\n");
+    *output += &composed;
+    c.advance(&composed);
+    c.ps = ParStatus::Element;
+}
+
+/// Split rows into at most `count` columns of about the same height.
+/// A column only starts at a breakable row, and not with blank rows unless it is the first.
+fn balance(breakable: &[bool], blank: &[bool], count: usize) -> Vec<Range<usize>> {
+    let rows = breakable.len();
+    let mut height = rows.div_ceil(count).max(1);
+    loop {
+        let mut columns: Vec<Range<usize>> = Vec::new();
+        let mut start = 0;
+        while start < rows {
+            if !columns.is_empty() {
+                while start < rows && blank[start] {
+                    start += 1;
+                }
+                if start == rows {
+                    break;
+                }
+            }
+            let mut end = (start + height).min(rows);
+            if end < rows {
+                // back off to where the next column can start, or go on until it can
+                end = (start + 1..=end).rev().find(|row| breakable[*row])
+                    .or_else(|| (end..rows).find(|row| breakable[*row]))
+                    .unwrap_or(rows);
+            }
+            columns.push(start..end);
+            start = end;
+        }
+        if columns.len() <= count {
+            return columns;
+        }
+        height += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{ Format, parse_input };
+
+    #[test]
+    fn balances_rows_over_columns() {
+        assert_eq!(balance(&[true; 6], &[false; 6], 2), [0..3, 3..6]);
+        assert_eq!(balance(&[true; 5], &[false; 5], 2), [0..3, 3..5]);
+        assert_eq!(balance(&[true; 7], &[false; 7], 3), [0..3, 3..6, 6..7]);
+        assert_eq!(balance(&[true; 4], &[false; 4], 1), vec![0..4]);
+        assert!(balance(&[], &[], 2).is_empty());
+    }
+
+    #[test]
+    fn columns_start_at_breakable_rows() {
+        // rows 2 and 3 belong to a box that starts at row 1
+        let breakable = [true, true, false, false, true, true];
+        assert_eq!(balance(&breakable, &[false; 6], 2), [0..4, 4..6]);
+        // a box that can't be split makes the columns longer
+        let breakable = [true, false, false, false, false, true];
+        assert_eq!(balance(&breakable, &[false; 6], 2), [0..5, 5..6]);
+    }
+
+    #[test]
+    fn columns_do_not_start_blank() {
+        let blank = [false, false, false, true, false, false];
+        assert_eq!(balance(&[true; 6], &blank, 2), [0..3, 4..6]);
+        let blank = [true, false, false, false];
+        assert_eq!(balance(&[true; 4], &blank, 2), [0..2, 2..4]);
+    }
+
+    #[test]
+    fn fits_lines() {
+        assert_eq!(fit_line("abc", 5), "abc  ");
+        assert_eq!(fit_line("abcdef", 3), "abc");
+        assert_eq!(fit_line("日本", 3), "日 ");
+        assert_eq!(fit_line(&format!("{RED}abcdef{RESET}"), 2), format!("{RED}ab{RESET}"));
+    }
+
+    #[test]
+    fn composes_columns() {
+        let composed = compose_columns(&[("a\nb", 3), ("c", 3)], 2);
+        let plain: Vec<String> = composed.split('\n').map(strip_ansi).collect();
+        assert_eq!(plain, ["a    c", "b"]);
+        // colours go on in the column, but not into the next one
+        let composed = compose_columns(&[(&format!("{RED}a\nb"), 1), ("c\nd", 1)], 1);
+        let lines: Vec<&str> = composed.split('\n').collect();
+        assert_eq!(lines[1], format!("{RED}b{RESET} d{RESET}"));
+    }
+
+    /// A document with navigation followed by a paragraph.
+    fn nav_doc() -> Doc {
+        let link = |text: &str| Link {
+            url: format!("#{text}"),
+            items: vec![LinkItem::String(text.to_string())],
+            ..Default::default()
+        };
+        let nav = Nav {
+            description: "Contents".to_string(),
+            links: vec![link("intro"), link("usage")],
+            ..Default::default()
+        };
+        let par = Paragraph {
+            items: vec![ParagraphItem::Text("Body text.".to_string())],
+            ..Default::default()
+        };
+        Doc { items: vec![DocItem::Nav(nav), DocItem::Paragraph(par)], ..Default::default() }
+    }
+
+    fn render_sidebar(sidebar: Sidebar, width: usize) -> (Vec<String>, SourceMap) {
+        let mut conf = Config { width, ..Config::compact() };
+        conf.nav.sidebar = sidebar;
+        let (output, map) = doc_to_ansi_string_with_source_map(&nav_doc(), &conf);
+        let lines = strip_ansi(&output).lines().map(|line| line.trim_end().to_string()).collect();
+        (lines, map)
+    }
+
+    /// Columns where the navigation links start.
+    fn link_cols(map: &SourceMap) -> Vec<usize> {
+        map.spans.iter()
+            .filter(|span| span.kind == NodeKind::NavLink)
+            .map(|span| span.start.1)
+            .collect()
+    }
+
+    #[test]
+    fn left_sidebar() {
+        // 24 columns of navigation and a gutter of 2 leave 44 for the body
+        let (lines, map) = render_sidebar(Sidebar::Left, 70);
+        assert_eq!(lines[0], format!("{:26}Body text.", "Contents"));
+        assert!(lines[1..].iter().any(|line| line.trim() == "intro"), "{lines:?}");
+        let par = map.spans.iter().find(|span| span.kind == NodeKind::Paragraph).expect("a body");
+        assert_eq!(par.start, (0, 26));
+        assert!(link_cols(&map).iter().all(|col| *col < 24), "{:?}", link_cols(&map));
+    }
+
+    #[test]
+    fn right_sidebar() {
+        let (lines, map) = render_sidebar(Sidebar::Right, 70);
+        assert_eq!(lines[0], format!("{:46}Contents", "Body text."));
+        let par = map.spans.iter().find(|span| span.kind == NodeKind::Paragraph).expect("a body");
+        assert_eq!(par.start, (0, 0));
+        assert!(link_cols(&map).iter().all(|col| *col >= 46), "{:?}", link_cols(&map));
+    }
+
+    #[test]
+    fn narrow_widths_put_the_sidebar_inline() {
+        // the body would be narrower than the 40 columns it needs at least
+        let (lines, map) = render_sidebar(Sidebar::Left, 65);
+        let (inline, inline_map) = render_sidebar(Sidebar::None, 65);
+        assert_eq!(lines, inline);
+        assert_eq!(map, inline_map);
+        assert_eq!(lines[0], "Contents");
+        assert_eq!(lines.last().map(String::as_str), Some("Body text."));
+        assert!(!render_sidebar(Sidebar::Right, 65).0[0].contains("Body"));
+        assert!(render_sidebar(Sidebar::Right, 66).0[0].contains("Body"));
+    }
+
+    fn render_measured(body_align: BodyAlign, width: usize) -> (Vec<String>, SourceMap) {
+        let mut conf = Config {
+            width, max_line_width: 20, body_align, body_margin: 3, ..Config::compact()
+        };
+        conf.text.word_wrap = true;
+        let text = "Lines of this text wrap at the measure.\n\nSecond paragraph.";
+        let doc = parse_input(text, Format::Markdown).expect("markdown always parses");
+        let (output, map) = doc_to_ansi_string_with_source_map(&doc, &conf);
+        (strip_ansi(&output).lines().map(|line| line.trim_end().to_string()).collect(), map)
+    }
+
+    #[test]
+    fn text_wraps_at_the_measure() {
+        let (lines, _) = render_measured(BodyAlign::Left, 80);
+        let text: Vec<&str> = lines.iter().map(|line| line.trim_start()).collect();
+        assert_eq!(text, ["Lines of this text", "wrap at the measure.", "Second paragraph."]);
+        // behind a margin of 3
+        assert!(lines.iter().all(|line| line.len() - line.trim_start().len() == 3), "{lines:?}");
+        // narrower than the measure, the text takes the whole width
+        let (lines, _) = render_measured(BodyAlign::Left, 18);
+        assert_eq!(lines[0], "Lines of this text");
+        assert_eq!(lines[1], "wrap at the");
+    }
+
+    #[test]
+    fn margins_centre_the_body() {
+        let (lines, map) = render_measured(BodyAlign::Centre, 60);
+        // 60 columns leave 40 for the margins, 20 on each side
+        assert_eq!(lines[0], format!("{:20}Lines of this text", ""));
+        assert_eq!(lines[1], format!("{:20}wrap at the measure.", ""));
+        assert_eq!(lines[2], format!("{:20}Second paragraph.", ""));
+        let (_, left_map) = render_measured(BodyAlign::Left, 60);
+        let starts = |map: &SourceMap| -> Vec<Position> {
+            map.spans.iter()
+                .filter(|span| span.kind == NodeKind::Paragraph)
+                .map(|span| span.start)
+                .collect()
+        };
+        assert_eq!(starts(&map), [(0, 20), (2, 20)]);
+        assert_eq!(starts(&left_map), [(0, 3), (2, 3)]);
+    }
+}
@@ -1,17 +1,41 @@
+#![forbid(unsafe_code)]
+
 use incodoc::*;
 
 use std::mem;
+use std::ops::Range;
+use std::collections::{ BTreeMap, BTreeSet };
 
 use zen_colour::*;
 use bat::{ PrettyPrinter, WrappingMode};
+use unicode_width::UnicodeWidthChar;
 
 use term_table::*;
 use term_table::row::Row;
 use term_table::table_cell::TableCell;
 
+pub mod bidi;
+pub mod checklist;
+pub mod compose;
 pub mod config;
+pub mod footnote;
+pub mod header;
+pub mod hyphenation;
+pub mod input;
+pub mod layout;
+pub mod line_break;
+pub mod punctuation;
+pub mod source_map;
+pub mod style;
+pub mod typography;
+pub mod viewer;
 
-use config::Config;
+use config::{
+    Align, ColourLevel, Config, ListConfig, NavStyle, NotePlacement, Numbering, PreOverflow,
+    Spacing, SpoilerTreatment, Style,
+};
+use footnote::*;
+use source_map::{ Checklist, NodeKind, NodePath, Position, SourceMap, Span };
 
 #[derive(Clone, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub struct Context {
@@ -21,10 +45,32 @@ pub struct Context {
     pub fg_mod_stack: Vec<String>,
     pub bg_mod_stack: Vec<String>,
     pub ii_stack: Vec<(usize, usize)>,
+    pub list_depth: usize,
     pub indentation: usize,
     pub indented: usize,
     pub width: usize,
     pub col: usize,
+    pub row: usize,
+    pub path: Vec<usize>,
+    pub source_map: Option<SourceMap>,
+    pub state: RenderState,
+    /// number of each footnote by the url that references it
+    pub footnotes: BTreeMap<String, usize>,
+    /// in preformatted text, which keeps its whitespace
+    pub pre: bool,
+    /// in inline code, which is kept as it is written
+    pub code: bool,
+    /// put in front of the next text
+    pub prefix: String,
+}
+
+/// State of an interactive view that changes how the document is rendered.
+#[derive(Clone, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct RenderState {
+    /// sections of which only the heading is shown
+    pub collapsed: BTreeSet<NodePath>,
+    /// link that is highlighted
+    pub focused: Option<NodePath>,
 }
 
 impl Context {
@@ -71,6 +117,43 @@ impl Context {
     pub fn set_ps_new(&mut self) {
         self.ps = ParStatus::New(preceding_newlines(self));
     }
+
+    pub fn pos(&self) -> Position {
+        (self.row, self.col)
+    }
+
+    /// Position where the next text will start, skipping indentation that is still pending.
+    pub fn text_pos(&self) -> Position {
+        if matches!(self.ps, ParStatus::New(_) | ParStatus::Newline(_)) {
+            (self.row, self.col + self.indentation - self.indented.min(self.indentation))
+        } else {
+            self.pos()
+        }
+    }
+
+    /// Record that everything from `start` up to the current position was rendered from the node
+    /// at the current path. Does nothing unless a source map is being built.
+    pub fn record_span(&mut self, kind: NodeKind, start: Position) {
+        let end = self.pos();
+        if let Some(map) = &mut self.source_map && start < end {
+            map.spans.push(Span { start, end, path: self.path.clone(), kind });
+        }
+    }
+
+    pub fn span_count(&self) -> usize {
+        self.source_map.as_ref().map(|map| map.spans.len()).unwrap_or(0)
+    }
+
+    /// Advance the position over already formatted text that was put into the output.
+    pub fn advance(&mut self, text: &str) {
+        match text.rfind('\n') {
+            Some(last) => {
+                self.row += text.matches('\n').count();
+                self.col = visible_len(&text[last + 1..]);
+            },
+            None => self.col += visible_len(text),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
@@ -84,6 +167,8 @@ pub enum ParStatus {
     Indentation,
     /// regular character
     Char,
+    /// character that no space follows, like an opening bracket
+    NoSpace,
     /// non-text element: code, list, table, image, etc
     Element,
     Emphasis,
@@ -109,44 +194,230 @@ pub fn doc_to_ansi_string(doc: &Doc, conf: &Config) -> String {
     res
 }
 
+/// Like `doc_to_ansi_string`, but also map the output back to the nodes of the document.
+pub fn doc_to_ansi_string_with_source_map(doc: &Doc, conf: &Config) -> (String, SourceMap) {
+    doc_to_ansi_string_with_state(doc, conf, RenderState::default())
+}
+
+/// Like `doc_to_ansi_string_with_source_map`, but rendered in the given interactive state.
+pub fn doc_to_ansi_string_with_state(doc: &Doc, conf: &Config, state: RenderState)
+    -> (String, SourceMap)
+{
+    let mut res = String::new();
+    let mut context = Context {
+        fg_mod: RESET.to_string(),
+        width: conf.width,
+        ps: ParStatus::New(1000),
+        source_map: Some(SourceMap::default()),
+        state,
+        ..Default::default()
+    };
+    doc_to_ansi(doc, conf, &mut context, &mut res);
+    (res, context.source_map.unwrap_or_default())
+}
+
 pub fn doc_to_ansi(doc: &Doc, conf: &Config, c: &mut Context, output: &mut String) {
-    for item in &doc.items {
+    if c.footnotes.is_empty() {
+        c.footnotes = number_footnotes(doc, conf);
+    }
+    let columns = layout::column_count(conf, c);
+    if layout::measure_applies(conf, c) {
+        layout::measured_doc_to_ansi(doc, conf, c, output);
+    } else if columns > 1 {
+        layout::columns_doc_to_ansi(doc, conf, columns, c, output);
+    } else if layout::sidebar_fits(doc, conf, c) {
+        layout::sidebar_doc_to_ansi(doc, conf, c, output);
+    } else {
+        doc_items_to_ansi(doc, conf, true, c, output);
+    }
+}
+
+/// Render the items of the document followed by its notes, leaving out navigation unless `navs`.
+pub fn doc_items_to_ansi(
+    doc: &Doc, conf: &Config, navs: bool, c: &mut Context, output: &mut String
+) {
+    header::header_to_ansi(doc, conf, c, output);
+    for (i, item) in doc.items.iter().enumerate() {
+        c.path.push(i);
         match item {
+            DocItem::Nav(_) if !navs => { },
             DocItem::Nav(nav) => nav_to_ansi(nav, conf, c, output),
+            DocItem::Paragraph(par) if is_skipped(&par.tags, conf) => { },
+            DocItem::Section(section) if is_skipped(&section.tags, conf) => { },
             DocItem::Paragraph(par) => {
+                newlines_minimum(1, false, c, output);
                 c.set_ps_new();
-                paragraph_to_ansi(par, conf, c, output);
+                let start = paragraph_to_ansi(par, conf, c, output);
+                c.record_span(NodeKind::Paragraph, start);
             },
+            DocItem::Section(section) if is_footnote_def(section) => { },
             DocItem::Section(section) => section_to_ansi(section, conf, c, output),
         }
+        c.path.pop();
     }
+    let defs = match conf.footnote.placement {
+        NotePlacement::Document => footnote_defs(doc, conf),
+        NotePlacement::Section => footnote_defs(doc, conf).into_iter()
+            .filter(|(path, _)| path.len() == 1)
+            .collect(),
+    };
+    notes_to_ansi(defs, conf, c, output);
 }
 
 pub fn nav_to_ansi(nav: &Nav, conf: &Config, c: &mut Context, output: &mut String) {
+    match conf.nav.style {
+        NavStyle::Plain => plain_nav_to_ansi(nav, conf, c, output),
+        NavStyle::Tree => tree_nav_to_ansi(nav, conf, c, output),
+        NavStyle::Breadcrumb => breadcrumb_nav_to_ansi(nav, conf, c, output),
+    }
+}
+
+pub fn plain_nav_to_ansi(nav: &Nav, conf: &Config, c: &mut Context, output: &mut String) {
     newlines_minimum(conf.nav.pre_description_mns + 1, false, c, output);
+    let nav_start = c.text_pos();
     text_to_ansi(&nav.description, conf, c, output);
     newlines(conf.nav.post_description_ns + 1, c, output);
 
-    for link in &nav.links {
+    for (i, link) in nav.links.iter().enumerate() {
         newlines_minimum(conf.nav.pre_link_mns + 1, false, c, output);
         c.push_indent(conf.nav.link_indent, 0);
+        c.path.push(i);
+        let start = c.text_pos();
         link_to_ansi(link, conf, c, output);
+        c.record_span(NodeKind::NavLink, start);
+        c.path.pop();
+        c.pop_indent();
+    }
+
+    for (i, sub) in nav.subs.iter().enumerate() {
+        c.push_indent(conf.nav.sub_indent, 0);
+        c.path.push(nav.links.len() + i);
+        plain_nav_to_ansi(sub, conf, c, output);
+        c.path.pop();
         c.pop_indent();
     }
+    c.record_span(NodeKind::Nav, nav_start);
+}
+
+pub fn tree_nav_to_ansi(nav: &Nav, conf: &Config, c: &mut Context, output: &mut String) {
+    newlines_minimum(conf.nav.pre_description_mns + 1, false, c, output);
+    let start = c.text_pos();
+    nav_label_to_ansi(&nav.description, conf, c, output);
+    nav_tree_children_to_ansi(nav, "", conf, c, output);
+    c.record_span(NodeKind::Nav, start);
+}
+
+/// Links and sub-navigation of a navigation tree, each behind a connector that follows `prefix`.
+pub fn nav_tree_children_to_ansi(
+    nav: &Nav, prefix: &str, conf: &Config, c: &mut Context, output: &mut String
+) {
+    let count = nav.links.len() + nav.subs.len();
+    for i in 0..count {
+        let last = i + 1 == count;
+        newlines_minimum(conf.nav.pre_link_mns + 1, false, c, output);
+        c.path.push(i);
+        indent(0, c, output);
+        let connector = format!("{prefix}{}", if last { "└── " } else { "├── " });
+        let width = connector.chars().count();
+        *output += FAINT;
+        append(&connector, c, output);
+        *output += RESET;
+        *output += &c.fg_mod;
+        // the span starts at the label, after the connector
+        let start = c.pos();
+        c.set_ps_new();
+        c.push_indent(width, width);
+        if i < nav.links.len() {
+            link_to_ansi(&nav.links[i], conf, c, output);
+            c.pop_indent();
+            c.record_span(NodeKind::NavLink, start);
+        } else {
+            let sub = &nav.subs[i - nav.links.len()];
+            nav_label_to_ansi(&sub.description, conf, c, output);
+            c.pop_indent();
+            let prefix = format!("{prefix}{}", if last { "    " } else { "│   " });
+            nav_tree_children_to_ansi(sub, &prefix, conf, c, output);
+            c.record_span(NodeKind::Nav, start);
+        }
+        c.path.pop();
+    }
+}
 
-    for sub in &nav.subs {
+pub fn breadcrumb_nav_to_ansi(nav: &Nav, conf: &Config, c: &mut Context, output: &mut String) {
+    newlines_minimum(conf.nav.pre_description_mns + 1, false, c, output);
+    let nav_start = c.text_pos();
+    nav_label_to_ansi(&nav.description, conf, c, output);
+    for (i, link) in nav.links.iter().enumerate() {
+        c.path.push(i);
+        if i > 0 || !nav.description.is_empty() {
+            *output += FAINT;
+            append(" › ", c, output);
+            *output += RESET;
+            *output += &c.fg_mod;
+            c.ps = ParStatus::Whitespace;
+        }
+        let start = c.text_pos();
+        link_to_ansi(link, conf, c, output);
+        c.record_span(NodeKind::NavLink, start);
+        c.path.pop();
+    }
+    for (i, sub) in nav.subs.iter().enumerate() {
         c.push_indent(conf.nav.sub_indent, 0);
-        nav_to_ansi(sub, conf, c, output);
+        c.path.push(nav.links.len() + i);
+        breadcrumb_nav_to_ansi(sub, conf, c, output);
+        c.path.pop();
         c.pop_indent();
     }
+    c.record_span(NodeKind::Nav, nav_start);
+}
+
+/// Description of a navigation, styled as the label of a group of links.
+pub fn nav_label_to_ansi(description: &str, conf: &Config, c: &mut Context, output: &mut String) {
+    c.push_fg_mod(BOLD, output);
+    text_to_ansi(description, conf, c, output);
+    c.pop_fg_mod(output);
 }
 
 pub fn section_to_ansi(section: &Section, conf: &Config, c: &mut Context, output: &mut String) {
-    if section.tags.contains("blockquote") || section.tags.contains("blockquote-typed") {
-        blockquote_to_ansi(section, conf, c, output);
-    } else {
-        headed_section_to_ansi(section, conf, c, output);
+    if is_excluded(&section.tags, conf) {
+        newlines_minimum(1, true, c, output);
+        placeholder_to_ansi(conf, c, output);
+        return;
     }
+    let pre = c.pre;
+    c.pre |= is_pre(&section.tags, conf);
+    let style = style::element_style("section", &section.tags, conf);
+    style::styled_to_ansi(&style, conf, c, output, |c, output| {
+        if section.tags.contains("blockquote") || section.tags.contains("blockquote-typed") {
+            blockquote_to_ansi(section, conf, c, output);
+        } else {
+            headed_section_to_ansi(section, conf, c, output);
+        }
+    });
+    c.pre = pre;
+}
+
+/// Whether an element with these tags is left out by `conf.filter`.
+pub fn is_excluded(tags: &Tags, conf: &Config) -> bool {
+    let has_any = |filter: &BTreeSet<String>| filter.iter().any(|tag| tags.contains(tag));
+    has_any(&conf.filter.exclude) && !has_any(&conf.filter.include)
+}
+
+/// Whether an element with these tags is left out without a placeholder.
+pub fn is_skipped(tags: &Tags, conf: &Config) -> bool {
+    conf.filter.placeholder.is_empty() && is_excluded(tags, conf)
+}
+
+/// Stands in for an element that is left out.
+pub fn placeholder_to_ansi(conf: &Config, c: &mut Context, output: &mut String) {
+    c.push_fg_mod(FAINT, output);
+    text_to_ansi(&conf.filter.placeholder, conf, c, output);
+    c.pop_fg_mod(output);
+}
+
+/// Whether an element with these tags is preformatted.
+pub fn is_pre(tags: &Tags, conf: &Config) -> bool {
+    conf.text.pre_tags.iter().any(|tag| tags.contains(tag))
 }
 
 pub fn headed_section_to_ansi(
@@ -154,9 +425,28 @@ pub fn headed_section_to_ansi(
 ) {
     c.set_ps_new();
     newlines_minimum(conf.headed_section.pre_heading_mns + 1, false, c, output);
+    let start = c.text_pos();
     heading_to_ansi(&section.heading, conf, c, output);
+    if c.state.collapsed.contains(&c.path) {
+        *output += FAINT;
+        append(" ...", c, output);
+        *output += RESET;
+        *output += &c.fg_mod;
+        c.record_span(NodeKind::Heading, start);
+        c.record_span(NodeKind::Section, start);
+        c.ps = ParStatus::Char;
+        return;
+    }
+    c.record_span(NodeKind::Heading, start);
     newlines(conf.headed_section.post_heading_ns + 1, c, output);
     section_body_to_ansi(section, conf, c, output);
+    if notes_at_section_end(conf, c) {
+        c.push_indent(conf.section.paragraph_indent, 0);
+        let defs = section_footnote_defs(section, &c.path, conf);
+        notes_to_ansi(defs, conf, c, output);
+        c.pop_indent();
+    }
+    c.record_span(NodeKind::Section, start);
 }
 
 pub fn heading_to_ansi(heading: &Heading, conf: &Config, c: &mut Context, output: &mut String) {
@@ -173,13 +463,23 @@ pub fn heading_to_ansi(heading: &Heading, conf: &Config, c: &mut Context, output
 pub fn section_body_to_ansi(
     section: &Section, conf: &Config, c: &mut Context, output: &mut String
 ) {
-    for item in &section.items {
+    for (i, item) in section.items.iter().enumerate() {
+        let skipped = match item {
+            SectionItem::Paragraph(par) => is_skipped(&par.tags, conf),
+            SectionItem::Section(section) => is_footnote_def(section)
+                || is_skipped(&section.tags, conf),
+        };
+        if skipped {
+            continue;
+        }
         newlines_minimum(conf.section.pre_item_mns + 1, false, c, output);
+        c.path.push(i);
         match item {
             SectionItem::Paragraph(par) => {
                 c.set_ps_new();
                 c.push_indent(conf.section.paragraph_indent, 0);
-                paragraph_to_ansi(par, conf, c, output);
+                let start = paragraph_to_ansi(par, conf, c, output);
+                c.record_span(NodeKind::Paragraph, start);
                 c.pop_indent();
             },
             SectionItem::Section(section) => {
@@ -188,88 +488,316 @@ pub fn section_body_to_ansi(
                 c.pop_indent();
             },
         }
+        c.path.pop();
     }
 }
 
 pub fn blockquote_to_ansi(section: &Section, conf: &Config, c: &mut Context, output: &mut String) {
+    let start = boxed_to_ansi(conf.blockquote.pre_quote_mns, c, output, |c, temp| {
+        if section.tags.contains("blockquote-typed") {
+            let start = c.text_pos();
+            heading_to_ansi(&section.heading, conf, c, temp);
+            c.record_span(NodeKind::Heading, start);
+            newline(c, temp);
+        }
+        section_body_to_ansi(section, conf, c, temp);
+    });
+    c.record_span(NodeKind::Blockquote, start);
+}
+
+/// Render something in a box with a thin border, after at least `pre_mns` empty lines.
+/// Gives where the box starts.
+pub fn boxed_to_ansi(
+    pre_mns: usize,
+    c: &mut Context,
+    output: &mut String,
+    render: impl FnOnce(&mut Context, &mut String),
+) -> Position {
     let mut table = term_table::Table::builder()
         .style(TableStyle::thin())
         .build();
     let mut row = Row::empty();
     let mut temp = String::new();
-    if section.tags.contains("blockquote-typed") {
-        heading_to_ansi(&section.heading, conf, c, &mut temp);
-        newline(c, &mut temp);
-    }
-    section_body_to_ansi(section, conf, c, &mut temp);
+    let first_span = c.span_count();
+    let first_checklist = c.source_map.as_ref().map(|map| map.checklists.len()).unwrap_or(0);
+    let (start_row, start_col) = c.pos();
+    // the content starts at the left of the box, not after what came before it on the row
+    c.col = 0;
+    // the box is indented and adds a border and padding on both sides
+    let width = c.width;
+    c.width = width.saturating_sub(c.indentation + 4).max(1);
+    render(c, &mut temp);
+    c.width = width;
     row.add_cell(TableCell::new(temp));
     table.add_row(row);
     let raw_table = table.render();
+    c.row = start_row;
+    c.col = start_col;
 
-    newlines_minimum(conf.blockquote.pre_quote_mns + 1, true, c, output);
+    newlines_minimum(pre_mns + 1, true, c, output);
+    // the boxed content starts after the top border and the left border with its padding
+    let start = c.text_pos();
+    if let Some(map) = &mut c.source_map {
+        map.shift(first_span, start.0 + 1 - start_row, start.1 + 2);
+        // the box is as wide as its content, so the lists in it can't be rendered again in place
+        map.checklists.truncate(first_checklist);
+    }
     *output += RESET;
     indent_table(&raw_table, c, output);
     *output += &c.fg_mod;
     c.ps = ParStatus::Element;
+    start
 }
 
-pub fn paragraph_to_ansi(par: &Paragraph, conf: &Config, c: &mut Context, output: &mut String) {
-    for item in &par.items {
-        match item {
+/// Gives where the paragraph starts.
+pub fn paragraph_to_ansi(par: &Paragraph, conf: &Config, c: &mut Context, output: &mut String)
+    -> Position
+{
+    let start = c.text_pos();
+    if is_excluded(&par.tags, conf) {
+        placeholder_to_ansi(conf, c, output);
+        return start;
+    }
+    let pre = c.pre;
+    c.pre |= is_pre(&par.tags, conf);
+    let composes = compose::composes(conf) || compose::is_rtl_paragraph(par, conf);
+    let style = style::element_style("paragraph", &par.tags, conf);
+    let boxed = style::styled_to_ansi(&style, conf, c, output, |c, output| {
+        if composes && !c.pre {
+            compose::composed_paragraph_to_ansi(par, conf, c, output);
+        } else {
+            paragraph_items_to_ansi(par, 0..par.items.len(), conf, c, output);
+        }
+    });
+    c.pre = pre;
+    boxed.unwrap_or(start)
+}
+
+/// Render the items of the paragraph in the range.
+pub fn paragraph_items_to_ansi(
+    par: &Paragraph, items: Range<usize>, conf: &Config, c: &mut Context, output: &mut String
+) {
+    for (i, item) in par.items.iter().enumerate().take(items.end).skip(items.start) {
+        if item_tags(item).is_some_and(|tags| is_excluded(tags, conf)) {
+            if !conf.filter.placeholder.is_empty() {
+                format_text_pre_before(&conf.filter.placeholder, conf, c, output);
+                placeholder_to_ansi(conf, c, output);
+            }
+            continue;
+        }
+        c.path.push(i);
+        let footnote_ref = matches!(item, ParagraphItem::Link(link) if is_footnote_ref(link));
+        if !footnote_ref && matches!(
+            item,
+            ParagraphItem::Text(_) | ParagraphItem::MText(_) | ParagraphItem::Em(_)
+                | ParagraphItem::Link(_)
+        ) {
+            // separate from the previous item first so the span starts at the text itself
+            format_text_pre_before(item_text_start(item), conf, c, output);
+        }
+        let start = c.text_pos();
+        let kind = match item {
             ParagraphItem::Text(text) => {
                 text_to_ansi(text, conf, c, output);
+                NodeKind::Text
             },
             ParagraphItem::MText(TextWithMeta { text, tags, .. }) => {
-                if tags.contains("code") {
+                let code = tags.contains("code");
+                let style = style::inline_style("text", tags, conf);
+                style::styled_to_ansi(&style, conf, c, output, |c, output| if code {
                     inline_code_to_ansi(text, conf, c, output);
                 } else {
                     text_to_ansi(text, conf, c, output);
-                }
+                });
+                if code { NodeKind::InlineCode } else { NodeKind::Text }
             },
             ParagraphItem::Em(emphasis) => {
-                emphasis_to_ansi(emphasis, conf, c, output);
+                let style = style::inline_style("emphasis", &emphasis.tags, conf);
+                style::styled_to_ansi(&style, conf, c, output, |c, output| {
+                    emphasis_to_ansi(emphasis, conf, c, output);
+                });
+                if is_spoiler(emphasis) { NodeKind::Spoiler } else { NodeKind::Emphasis }
+            },
+            ParagraphItem::Link(link) if footnote_ref => {
+                footnote_ref_to_ansi(link, conf, c, output);
+                NodeKind::Link
             },
             ParagraphItem::Link(link) => {
-                link_to_ansi(link, conf, c, output);
+                let style = style::inline_style("link", &link.tags, conf);
+                style::styled_to_ansi(&style, conf, c, output, |c, output| {
+                    link_to_ansi(link, conf, c, output);
+                });
+                NodeKind::Link
             },
             ParagraphItem::Code(code) => {
                 code_to_ansi(code, conf, c, output);
+                NodeKind::CodeBlock
             },
             ParagraphItem::List(list) => {
+                let checklist = (list.ltype == ListType::Checked && c.source_map.is_some())
+                    .then(|| Checklist {
+                        path: c.path.clone(),
+                        ps: c.ps,
+                        row: c.row,
+                        col: c.col,
+                        indentation: c.indentation,
+                        indented: c.indented,
+                        width: c.width,
+                        list_depth: c.list_depth,
+                        fg_mod: c.fg_mod.clone(),
+                        bg_mod: c.bg_mod.clone(),
+                        ..Default::default()
+                    });
+                let first_span = c.span_count();
                 list_to_ansi(list, conf, c, output);
+                if let Some(mut checklist) = checklist
+                    && let Some(map) = &mut c.source_map
+                    && let Some(first_row) = map.spans[first_span..].iter()
+                        .map(|span| span.start.0)
+                        .min()
+                {
+                    checklist.first_row = first_row;
+                    checklist.rows = (first_row..=c.row).map(|row| (row, 0)).collect();
+                    map.checklists.push(checklist);
+                }
+                NodeKind::List
             },
             ParagraphItem::Table(table) => {
                 table_to_ansi(table, conf, c, output);
+                NodeKind::Table
             },
-        }
+        };
+        c.record_span(kind, start);
+        c.path.pop();
+    }
+}
+
+/// Tags of a paragraph item, if it has any.
+fn item_tags(item: &ParagraphItem) -> Option<&Tags> {
+    match item {
+        ParagraphItem::Text(_) | ParagraphItem::Code(Err(_)) => None,
+        ParagraphItem::MText(TextWithMeta { tags, .. }) => Some(tags),
+        ParagraphItem::Em(em) => Some(&em.tags),
+        ParagraphItem::Link(link) => Some(&link.tags),
+        ParagraphItem::Code(Ok(code)) => Some(&code.tags),
+        ParagraphItem::List(list) => Some(&list.tags),
+        ParagraphItem::Table(table) => Some(&table.tags),
+    }
+}
+
+/// Text an inline paragraph item starts with.
+fn item_text_start(item: &ParagraphItem) -> &str {
+    match item {
+        // code is spaced from what comes before it, whatever it starts with
+        ParagraphItem::MText(TextWithMeta { tags, .. }) if tags.contains("code") => "",
+        ParagraphItem::Text(text) | ParagraphItem::MText(TextWithMeta { text, .. }) => text,
+        ParagraphItem::Em(em) => &em.text,
+        ParagraphItem::Link(link) => match link.items.first() {
+            Some(LinkItem::String(text)) => text,
+            Some(LinkItem::Em(em)) => &em.text,
+            None => "",
+        },
+        _ => "",
     }
 }
 
 pub fn list_to_ansi(list: &List, conf: &Config, c: &mut Context, output: &mut String) {
-    let width = match list.ltype {
-        ListType::Distinct => format!("{}", list.items.len().max(1) - 1).len(),
-        ListType::Identical => 2,
-        ListType::Checked => 6,
-    };
-    let iwidth = match list.ltype {
-        ListType::Distinct => width + 2,
-        ListType::Identical | ListType::Checked => width,
+    let markers = list_markers(list, &conf.list, c.list_depth);
+    let mwidth = markers.iter().map(|marker| marker.chars().count()).max().unwrap_or(0);
+    let iwidth = mwidth + 1 + conf.list.hanging_indent;
+    let loose = list_is_loose(list);
+    let marker_style = Style {
+        fg: conf.list.marker_style.fg,
+        bg: conf.list.marker_style.bg,
+        ..Default::default()
     };
-    for (count, par) in list.items.iter().enumerate() {
-        newlines_minimum(conf.list.pre_item_mns + 1, false, c, output);
-        indent(0, c, output);
-        match list.ltype {
-            ListType::Distinct => append(&format!("{count:>width$}. "), c, output),
-            ListType::Identical => append("- ", c, output),
-            ListType::Checked if par.tags.contains("checked") => append("- [x] ", c, output),
-            ListType::Checked => append("- [ ] ", c, output),
+    c.list_depth += 1;
+    for (count, (par, marker)) in list.items.iter().zip(markers).enumerate() {
+        if is_skipped(&par.tags, conf) {
+            continue;
         }
+        let mns = if loose && count > 0 {
+            conf.list.loose_pre_item_mns
+        } else {
+            conf.list.pre_item_mns
+        };
+        newlines_minimum(mns + 1, false, c, output);
+        c.path.push(count);
+        let start = c.text_pos();
+        indent(0, c, output);
+        style::styled_to_ansi(&marker_style, conf, c, output, |c, output| {
+            match conf.list.marker_align {
+                Align::Left => append(&format!("{marker:<mwidth$}"), c, output),
+                Align::Right => append(&format!("{marker:>mwidth$}"), c, output),
+            }
+        });
+        append(&" ".repeat(iwidth - mwidth), c, output);
         c.set_ps_new();
         c.push_indent(iwidth, iwidth);
         paragraph_to_ansi(par, conf, c, output);
         c.pop_indent();
         c.ps = ParStatus::Element;
+        c.record_span(NodeKind::ListItem, start);
+        c.path.pop();
     }
+    c.list_depth -= 1;
+}
+
+/// A list is loose when any item has multiple blocks: text, code blocks and tables.
+/// Nested lists don't count, so a list of short items with sub-lists stays tight.
+pub fn list_is_loose(list: &List) -> bool {
+    list.items.iter().any(|par| {
+        let mut blocks = 0;
+        let mut in_text = false;
+        for item in &par.items {
+            match item {
+                // the paragraphs of an item end up in one text, separated by an empty line
+                ParagraphItem::Text(text) => for (i, part) in text.split("\n\n").enumerate() {
+                    if i > 0 {
+                        in_text = false;
+                    }
+                    if !part.trim().is_empty() {
+                        if !in_text {
+                            blocks += 1;
+                        }
+                        in_text = true;
+                    }
+                },
+                ParagraphItem::MText(_) | ParagraphItem::Em(_) | ParagraphItem::Link(_) => {
+                    if !in_text {
+                        blocks += 1;
+                    }
+                    in_text = true;
+                },
+                ParagraphItem::Code(_) | ParagraphItem::Table(_) => {
+                    blocks += 1;
+                    in_text = false;
+                },
+                ParagraphItem::List(_) => in_text = false,
+            }
+        }
+        blocks > 1
+    })
+}
+
+/// Markers of the items of a list, without the space that separates them from the item.
+pub fn list_markers(list: &List, conf: &ListConfig, depth: usize) -> Vec<String> {
+    let by_depth = |markers: &[String]| {
+        if markers.is_empty() { String::new() } else { markers[depth % markers.len()].clone() }
+    };
+    list.items.iter().enumerate().map(|(count, par)| match list.ltype {
+        ListType::Distinct => {
+            let numbering = if conf.numbering.is_empty() {
+                Numbering::Decimal
+            } else {
+                conf.numbering[depth % conf.numbering.len()]
+            };
+            format!("{}.", numbering.format(conf.start + count))
+        },
+        ListType::Identical => by_depth(&conf.bullets),
+        ListType::Checked if par.tags.contains("checked") => conf.checked.clone(),
+        ListType::Checked => conf.unchecked.clone(),
+    }).collect()
 }
 
 pub fn table_to_ansi(table: &incodoc::Table, conf: &Config, c: &mut Context, output: &mut String) {
@@ -277,8 +805,9 @@ pub fn table_to_ansi(table: &incodoc::Table, conf: &Config, c: &mut Context, out
     for row in &table.rows {
         max_cols = max_cols.max(row.items.len());
     }
-    let available_width = c.width - c.indentation - max_cols - 1;
-    let col_width = available_width / max_cols - 2; // the -2 is for the padding (which is optional)
+    let available_width = c.width.saturating_sub(c.indentation + max_cols + 1);
+    // the -2 is for the padding (which is optional)
+    let col_width = (available_width / max_cols.max(1)).saturating_sub(2).max(1);
     let mut t = term_table::Table::builder()
         .style(TableStyle::thin())
         .build();
@@ -288,6 +817,8 @@ pub fn table_to_ansi(table: &incodoc::Table, conf: &Config, c: &mut Context, out
             let mut temp = String::new();
             let mut table_context = Context {
                 width: col_width,
+                footnotes: c.footnotes.clone(),
+                pre: c.pre,
                 ..Default::default()
             };
             paragraph_to_ansi(item, conf, &mut table_context, &mut temp);
@@ -318,6 +849,8 @@ pub fn indent_table(raw_table: &str, c: &mut Context, output: &mut String) {
     for _ in 0..indent_string_1.len() {
         res.pop();
     }
+    c.col = 0;
+    c.advance(&res);
     *output += &res;
 }
 
@@ -335,12 +868,18 @@ pub fn code_to_ansi(
 
     match code {
         Ok(code) => {
+            let theme = match conf.colour_level {
+                ColourLevel::Basic => "ansi",
+                _ => &conf.code_block.theme,
+            };
             let res = PrettyPrinter::new()
                 .input_from_bytes(code.code.as_bytes())
                 .language(&code.language)
-                .theme("ansi")
-                .term_width(c.width - c.indentation - conf.code_block.indent)
+                .theme(theme)
+                .true_color(conf.colour_level == ColourLevel::TrueColour)
+                .term_width(c.width.saturating_sub(c.indentation + conf.code_block.indent).max(1))
                 .line_numbers(true)
+                .tab_width(Some(conf.tab_width))
                 .use_italics(true)
                 .wrapping_mode(WrappingMode::Character)
                 .print_with_writer(Some(&mut temp));
@@ -370,22 +909,30 @@ pub fn code_to_ansi(
 
     newlines_minimum(conf.code_block.pre_code_block_mns + 1, true, c, output);
     *output += RESET;
+    c.advance(&temp);
     *output += &temp;
     *output += &c.fg_mod;
     c.ps = ParStatus::Element;
 }
 
+/// Render code in text as it is written: punctuation and whitespace are left alone.
 pub fn inline_code_to_ansi(text: &str, conf: &Config, c: &mut Context, output: &mut String) {
-    format_text_pre(c, output);
+    format_text_pre_before("", conf, c, output);
     *output += RESET;
     c.push_bg_mod(BG_BLACK, output);
+    let code = mem::replace(&mut c.code, true);
     format_text_main(text, conf, c, output);
+    c.code = code;
     c.pop_bg_mod(output);
     c.ps = ParStatus::Char;
 }
 
 pub fn link_to_ansi(link: &Link, conf: &Config, c: &mut Context, output: &mut String) {
-    c.push_fg_mod(MAGENTA, output);
+    if c.state.focused.as_ref() == Some(&c.path) {
+        c.push_fg_mod(&format!("{MAGENTA}{EFFECT7}"), output);
+    } else {
+        c.push_fg_mod(MAGENTA, output);
+    }
     for item in &link.items {
         match item {
             LinkItem::String(text) => text_to_ansi(text, conf, c, output),
@@ -402,24 +949,88 @@ pub fn emphasis_to_ansi(em: &Emphasis, conf: &Config, c: &mut Context, output: &
         (EmType::Emphasis, EmStrength::Strong) => format!("{ITALIC}{BOLD}"),
         (EmType::Deemphasis, EmStrength::Light) => FAINT.to_string(),
         (EmType::Deemphasis, EmStrength::Medium) => CROSSED.to_string(),
-        (EmType::Deemphasis, EmStrength::Strong) => HIDDEN.to_string(),
+        (EmType::Deemphasis, EmStrength::Strong) => {
+            spoiler_to_ansi(em, conf, c, output);
+            return;
+        },
     };
     *output += &modifier;
-    format_text(&em.text, conf, c, output);
+    format_text(&typeset(&em.text, conf, c), conf, c, output);
+    *output += RESET;
+    *output += &c.fg_mod;
+    c.ps = ParStatus::Emphasis;
+}
+
+/// Strongly deemphasised text according to `conf.spoiler`.
+pub fn spoiler_to_ansi(em: &Emphasis, conf: &Config, c: &mut Context, output: &mut String) {
+    let focused = c.state.focused.as_ref() == Some(&c.path);
+    let (modifier, masked) = match conf.spoiler.treatment {
+        _ if conf.spoiler.reveal => ("", false),
+        SpoilerTreatment::Hidden => (HIDDEN, false),
+        SpoilerTreatment::Mask => ("", true),
+        SpoilerTreatment::Dim => (FAINT, false),
+        SpoilerTreatment::Focus => (FAINT, !focused),
+    };
+    *output += modifier;
+    let text = typeset(&em.text, conf, c);
+    if masked {
+        let mask = |ch: char| if ch.is_whitespace() { ch } else { conf.spoiler.mask };
+        format_text(&text.chars().map(mask).collect::<String>(), conf, c, output);
+    } else {
+        format_text(&text, conf, c, output);
+    }
     *output += RESET;
     *output += &c.fg_mod;
     c.ps = ParStatus::Emphasis;
 }
 
+/// Whether the emphasis is a spoiler.
+pub fn is_spoiler(em: &Emphasis) -> bool {
+    em.etype == EmType::Deemphasis && em.strength == EmStrength::Strong
+}
+
 pub fn text_to_ansi(text: &str, conf: &Config, c: &mut Context, output: &mut String) {
-    format_text(text, conf, c, output);
+    format_text(&typeset(text, conf, c), conf, c, output);
+}
+
+/// Prose with smart typography when it is configured.
+fn typeset<'a>(text: &'a str, conf: &Config, c: &Context) -> std::borrow::Cow<'a, str> {
+    if !conf.text.smart_typography {
+        return text.into();
+    }
+    // a quote that isn't spaced from the word before closes it
+    let after_word = c.ps == ParStatus::Emphasis
+        || (c.ps == ParStatus::Char && punctuation::joins_previous(text, &conf.text));
+    typography::smarten(text, after_word).into()
 }
 
 pub fn format_text(text: &str, conf: &Config, c: &mut Context, output: &mut String) {
-    format_text_pre(c, output);
+    format_text_pre_before(text, conf, c, output);
     format_text_main(text, conf, c, output);
 }
 
+/// Like `format_text_pre`, but no space is put before closing punctuation or between characters of
+/// scripts that don't use them.
+pub fn format_text_pre_before(
+    next: &str, conf: &Config, c: &mut Context, output: &mut String
+) {
+    // preformatted items follow each other as they are
+    if c.pre {
+        return;
+    }
+    let last = output.rsplit('\n').next().and_then(|line| strip_ansi(line).chars().last());
+    let cjk = matches!(
+        (last, next.chars().next()),
+        (Some(last), Some(next)) if !line_break::space_between(last, next)
+    );
+    if c.ps == ParStatus::Char && c.col < c.width
+        && (cjk || punctuation::joins_previous(next, &conf.text))
+    {
+        return;
+    }
+    format_text_pre(c, output);
+}
+
 pub fn format_text_pre(c: &mut Context, output: &mut String) {
     if c.col >= c.width {
         newline(c, output);
@@ -438,26 +1049,57 @@ pub fn format_text_pre(c: &mut Context, output: &mut String) {
     }
 }
 
+const NBSP: char = '\u{A0}';
+
 pub fn format_text_main(text: &str, conf: &Config, c: &mut Context, output: &mut String) {
     for ch in text.chars() {
-        if c.col >= c.width {
+        let width = char_width(ch);
+        let full = (c.col >= c.width && width > 0) || c.col + width > c.width;
+        if full && !(c.pre && ch == '\n') {
+            if c.pre && conf.text.pre_overflow == PreOverflow::Truncate {
+                continue;
+            }
             newline(c, output);
         }
         if matches!(c.ps, ParStatus::Newline(_) | ParStatus::New(_)) {
             indent(0, c, output);
         }
+        if !c.prefix.is_empty() && !ch.is_whitespace() {
+            let prefix = mem::take(&mut c.prefix);
+            append(&prefix, c, output);
+            c.ps = if prefix.ends_with(' ') { ParStatus::Whitespace } else { ParStatus::Char };
+        }
         match ch {
+            '\n' if c.pre => newline(c, output),
             '\n' => {
-                if c.ps == ParStatus::Whitespace || c.ps == ParStatus::Char {
+                if matches!(c.ps, ParStatus::Whitespace | ParStatus::Char | ParStatus::NoSpace) {
                     c.ps = ParStatus::Newline(1);
                     c.col = 0;
+                    c.row += 1;
                     output.push('\n');
                 }
             },
             '\r' => {},
+            // kept only for composition, which may break the word there
+            hyphenation::SOFT_HYPHEN if c.width != compose::UNBOUNDED => { },
+            '\t' if conf.tab_width > 0 => {
+                let len = conf.tab_width - c.col.saturating_sub(c.indentation) % conf.tab_width;
+                if c.col + len >= c.width {
+                    newline(c, output);
+                } else {
+                    *output += &" ".repeat(len);
+                    c.col += len;
+                    c.ps = ParStatus::Whitespace;
+                }
+            },
+            x if c.pre || c.code => {
+                output.push(if x.is_whitespace() { ' ' } else { x });
+                c.col += if x.is_whitespace() { 1 } else { width };
+                c.ps = if x.is_whitespace() { ParStatus::Whitespace } else { ParStatus::Char };
+            },
             x => {
                 if x.is_whitespace() {
-                    if c.ps != ParStatus::Whitespace {
+                    if !matches!(c.ps, ParStatus::Whitespace | ParStatus::NoSpace) {
                         if !matches!(c.ps, ParStatus::Newline(_)) {
                             output.push(' ');
                             c.col += 1;
@@ -465,15 +1107,43 @@ pub fn format_text_main(text: &str, conf: &Config, c: &mut Context, output: &mut
                         c.ps = ParStatus::Whitespace;
                     }
                 } else {
-                    if conf.text.swallow_whitespace && c.ps == ParStatus::Whitespace
-                        && conf.text.whitespace_swallowers.contains(&x.to_string())
-                    {
+                    let spacing = match punctuation::spacing(x, &conf.text) {
+                        Some(Spacing::Quote) if matches!(
+                            c.ps,
+                            ParStatus::Char | ParStatus::Emphasis
+                        ) => Some(Spacing::NoSpaceBefore),
+                        Some(Spacing::Quote) => Some(Spacing::NoSpaceAfter),
+                        spacing => spacing,
+                    };
+                    let no_space = matches!(
+                        spacing,
+                        Some(Spacing::NoSpaceBefore | Spacing::NbspBefore)
+                    );
+                    let space = c.ps == ParStatus::Whitespace && output.ends_with(' ');
+                    if space && no_space {
                         output.pop();
                         c.col -= 1;
                     }
+                    if spacing == Some(Spacing::NbspBefore)
+                        && (space || matches!(c.ps, ParStatus::Char | ParStatus::Emphasis))
+                    {
+                        output.push(NBSP);
+                        c.col += 1;
+                    }
                     c.ps = ParStatus::Char;
-                    c.col += 1;
+                    c.col += width;
                     output.push(x);
+                    match spacing {
+                        Some(Spacing::NoSpaceAfter) => c.ps = ParStatus::NoSpace,
+                        Some(Spacing::NbspAfter) => {
+                            if c.col < c.width {
+                                output.push(NBSP);
+                                c.col += 1;
+                            }
+                            c.ps = ParStatus::NoSpace;
+                        },
+                        _ => { },
+                    }
                 }
             },
         }
@@ -481,12 +1151,19 @@ pub fn format_text_main(text: &str, conf: &Config, c: &mut Context, output: &mut
 }
 
 pub fn append(text: &str, c: &mut Context, output: &mut String) {
-    let len = text.len();
-    if len + c.col < c.width {
+    let len = visible_len(text);
+    if len + c.col < c.width || c.col >= c.width {
         *output += text;
         c.col += len;
     } else {
-        let first = len + c.col - c.width;
+        let mut used = c.col;
+        let first = text.char_indices()
+            .find(|(_, ch)| {
+                used += char_width(*ch);
+                used > c.width
+            })
+            .map(|(i, _)| i)
+            .unwrap_or(text.len());
         *output += &text[..first];
         newline(c, output);
         append(&text[first..], c, output);
@@ -524,6 +1201,7 @@ pub fn newlines(n: usize, c: &mut Context, output: &mut String) {
     }
     let already = preceding_newlines(c);
     c.ps = ParStatus::Newline(already + n);
+    c.row += n;
     c.col = 0;
     c.indented = 0;
 }
@@ -538,6 +1216,523 @@ pub fn newlines_minimum(newlines: usize, skip_if_new: bool, c: &mut Context, out
         *output += "\n";
     }
     c.ps = ParStatus::Newline(already + todo);
+    c.row += todo;
     c.col = 0;
     c.indented = 0;
 }
+
+/// Width of the text in terminal columns, not counting ANSI escape sequences.
+pub fn visible_len(text: &str) -> usize {
+    strip_ansi(text).chars().map(char_width).sum()
+}
+
+/// Number of terminal columns a character takes up: two for wide East Asian characters, none for
+/// combining marks and control characters.
+pub fn char_width(ch: char) -> usize {
+    ch.width().unwrap_or(0)
+}
+
+/// Remove ANSI escape sequences from the text.
+pub fn strip_ansi(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut escaped = false;
+    for ch in text.chars() {
+        if escaped {
+            if ch.is_ascii_alphabetic() {
+                escaped = false;
+            }
+        } else if ch == '\x1B' {
+            escaped = true;
+        } else {
+            res.push(ch);
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{ Format, parse_input };
+
+    fn render_with(md: &str, conf: &Config) -> Vec<String> {
+        let doc = parse_input(md, Format::Markdown).expect("markdown always parses");
+        strip_ansi(&doc_to_ansi_string(&doc, conf)).lines().map(String::from).collect()
+    }
+
+    fn render(md: &str, width: usize, word_wrap: bool) -> Vec<String> {
+        let mut conf = Config { width, ..Default::default() };
+        conf.text.word_wrap = word_wrap;
+        render_with(md, &conf)
+    }
+
+    #[test]
+    fn korean_keeps_spaces() {
+        for word_wrap in [false, true] {
+            assert_eq!(
+                render("안녕하세요 세계 여러분 반갑습니다", 80, word_wrap),
+                ["안녕하세요 세계 여러분 반갑습니다"],
+            );
+        }
+    }
+
+    #[test]
+    fn mixed_cjk_and_latin() {
+        for word_wrap in [false, true] {
+            assert_eq!(
+                render("日本語**強調**です and **bold** text, hello 世界", 80, word_wrap),
+                ["日本語強調です and bold text, hello 世界"],
+            );
+        }
+    }
+
+    #[test]
+    fn wide_characters_fit_the_width() {
+        let text = "日本語の文章です。今日は晴れ。「雨」でしょう！これはテストの文章です。";
+        for word_wrap in [false, true] {
+            let lines = render(text, 15, word_wrap);
+            assert!(lines.iter().all(|line| visible_len(line) <= 15), "{lines:?}");
+            assert_eq!(lines.concat(), text);
+        }
+        // no line starts with closing punctuation or ends with opening punctuation
+        for line in render(text, 15, true) {
+            assert!(!line.starts_with(['。', '」', '！', 'ょ']), "{line}");
+            assert!(!line.ends_with('「'), "{line}");
+        }
+    }
+
+    #[test]
+    fn list_items_with_sublists_are_wrapped() {
+        let md = "- plain item words that will wrap\n  - sub item with some words that wrap";
+        assert_eq!(
+            render(md, 20, true),
+            [
+                "• plain item words",
+                "  that will wrap",
+                "  ◦ sub item with",
+                "    some words that",
+                "    wrap",
+            ],
+        );
+    }
+
+    #[test]
+    fn punctuation_leaves_code_alone() {
+        for language in ["fr", "en"] {
+            for word_wrap in [false, true] {
+                let mut conf = Config { width: 80, ..Default::default() };
+                conf.text.language = language.to_string();
+                conf.text.word_wrap = word_wrap;
+                let md = "see `http://x.fr/a:b` and `f( a , b )` or `:wq` ; done";
+                let line = render_with(md, &conf).concat();
+                for code in ["http://x.fr/a:b", "f( a , b )", " :wq"] {
+                    assert!(line.contains(code), "{language}: {line}");
+                }
+                let md = "```rust\nf( a , b ) ; \"x\" !\n```";
+                let lines = render_with(md, &conf).join("\n");
+                assert!(lines.contains("f( a , b ) ; \"x\" !"), "{language}: {lines}");
+            }
+        }
+    }
+
+    #[test]
+    fn narrow_widths_leave_tables_and_code_whole() {
+        let md = "- item\n\n  | a | b | c |\n  |---|---|---|\n  | 1 | 2 | 3 |\n\n\
+            \x20 ```rust\n  fn f() {}\n  ```";
+        for width in [0, 1, 5, 20] {
+            let text = render(md, width, false).join("\n");
+            assert!(text.contains('a') && text.contains('3') && text.contains('f'), "{text}");
+        }
+    }
+
+    #[test]
+    fn nav_link_spans_start_at_the_label() {
+        let link = |text: &str| Link {
+            url: format!("#{text}"),
+            items: vec![LinkItem::String(text.to_string())],
+            ..Default::default()
+        };
+        let nav = Nav {
+            description: "Contents".to_string(),
+            links: vec![link("intro"), link("usage")],
+            subs: vec![Nav {
+                description: "More".to_string(),
+                links: vec![link("details")],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let doc = Doc { items: vec![DocItem::Nav(nav)], ..Default::default() };
+        for style in [NavStyle::Plain, NavStyle::Tree, NavStyle::Breadcrumb] {
+            let mut conf = Config { width: 40, ..Default::default() };
+            conf.nav.style = style;
+            let (output, map) = doc_to_ansi_string_with_source_map(&doc, &conf);
+            let lines: Vec<String> = output.split('\n').map(strip_ansi).collect();
+            let labels: Vec<String> = map.spans.iter()
+                .filter(|span| span.kind == NodeKind::NavLink)
+                .map(|span| lines[span.start.0].chars().skip(span.start.1).take(5).collect())
+                .collect();
+            assert_eq!(labels, ["intro", "usage", "detai"], "{style:?}");
+            assert!(!map.spans.iter().any(|span| span.kind == NodeKind::Link));
+        }
+    }
+
+    /// A document of text with a spoiler in it.
+    fn spoiler_doc() -> Doc {
+        let spoiler = Emphasis {
+            strength: EmStrength::Strong,
+            etype: EmType::Deemphasis,
+            text: "the butler did it".to_string(),
+            tags: Tags::default(),
+            props: Props::default(),
+        };
+        let par = Paragraph {
+            items: vec![
+                ParagraphItem::Text("So".to_string()),
+                ParagraphItem::Em(spoiler),
+                ParagraphItem::Text("!".to_string()),
+            ],
+            ..Default::default()
+        };
+        Doc { items: vec![DocItem::Paragraph(par)], ..Default::default() }
+    }
+
+    fn render_spoiler(treatment: SpoilerTreatment, reveal: bool, state: RenderState)
+        -> (String, SourceMap)
+    {
+        let mut conf = Config::default();
+        conf.spoiler.treatment = treatment;
+        conf.spoiler.reveal = reveal;
+        doc_to_ansi_string_with_state(&spoiler_doc(), &conf, state)
+    }
+
+    #[test]
+    fn spoiler_treatments() {
+        let plain = |treatment| render_spoiler(treatment, false, RenderState::default()).0;
+        let output = plain(SpoilerTreatment::Hidden);
+        assert!(output.contains(&format!("{HIDDEN}the butler did it")), "{output:?}");
+        let output = plain(SpoilerTreatment::Mask);
+        assert_eq!(strip_ansi(&output).trim_end(), "So ███ ██████ ███ ██!");
+        let output = plain(SpoilerTreatment::Dim);
+        assert!(output.contains(&format!("{FAINT}the butler did it")), "{output:?}");
+        let output = plain(SpoilerTreatment::Focus);
+        assert!(output.contains(&format!("{FAINT}███ ██████ ███ ██")), "{output:?}");
+        // a focused spoiler is shown, faint
+        let state = RenderState { focused: Some(vec![0, 1]), ..Default::default() };
+        let output = render_spoiler(SpoilerTreatment::Focus, false, state).0;
+        assert!(output.contains(&format!("{FAINT}the butler did it")), "{output:?}");
+    }
+
+    #[test]
+    fn revealed_spoilers() {
+        for treatment in [
+            SpoilerTreatment::Hidden,
+            SpoilerTreatment::Mask,
+            SpoilerTreatment::Dim,
+            SpoilerTreatment::Focus,
+        ] {
+            let output = render_spoiler(treatment, true, RenderState::default()).0;
+            assert!(!output.contains(HIDDEN) && !output.contains(FAINT), "{output:?}");
+            assert_eq!(strip_ansi(&output).trim_end(), "So the butler did it!", "{treatment:?}");
+        }
+    }
+
+    #[test]
+    fn masked_spoiler_spans() {
+        let (output, map) = render_spoiler(SpoilerTreatment::Mask, false, RenderState::default());
+        let spoiler = map.spans.iter()
+            .find(|span| span.kind == NodeKind::Spoiler)
+            .expect("a spoiler span");
+        assert_eq!((spoiler.start, spoiler.end), ((0, 3), (0, 20)));
+        assert_eq!(spoiler.path, [0, 1]);
+        let line: Vec<char> = strip_ansi(&output).chars().collect();
+        assert_eq!(line[3..20].iter().collect::<String>(), "███ ██████ ███ ██");
+        assert_eq!(map.node_at(0, 10).map(|span| span.kind), Some(NodeKind::Spoiler));
+    }
+
+    fn render_tabs(md: &str, tab_width: usize) -> Vec<String> {
+        render_with(md, &Config { width: 40, tab_width, ..Default::default() })
+    }
+
+    #[test]
+    fn tabs_expand_to_stops_from_the_indentation() {
+        assert_eq!(render_tabs("a\tbc\td", 4), ["a   bc  d"]);
+        assert_eq!(render_tabs("a\tbc\td", 3), ["a  bc d"]);
+        assert_eq!(render_tabs("`x\ty` ab\tc `x\ty`", 4), ["x   y ab    c x y"]);
+        // the stops are counted from where the text of the item starts
+        assert_eq!(
+            render_tabs("- ab\tc\n  - x\ty", 4),
+            ["• ab  c", "  ◦ x   y"],
+        );
+        let code = render_tabs("```rust\nfn f() {\n\tlet x;\t// x\n}\n```", 2);
+        assert_eq!(code[1].trim_start(), "2   let x;  // x");
+        let code = render_tabs("```rust\nfn f() {\n\tlet x;\t// x\n}\n```", 4);
+        assert_eq!(code[1].trim_start(), "2     let x;  // x");
+    }
+
+    #[test]
+    fn tabs_without_a_width() {
+        // text collapses them like other whitespace, code keeps them
+        assert_eq!(render_tabs("a\t\tb", 0), ["a b"]);
+        let code = render_tabs("```rust\nfn f() {\n\tlet x;\n}\n```", 0);
+        assert_eq!(code[1].trim_start(), "2 \tlet x;");
+    }
+
+    /// A document of paragraphs of text, those of which the flag is set tagged as preformatted.
+    fn pre_doc(pars: &[(&str, bool)]) -> Doc {
+        let items = pars.iter().map(|(text, pre)| {
+            let mut par = Paragraph {
+                items: vec![ParagraphItem::Text(text.to_string())],
+                ..Default::default()
+            };
+            if *pre {
+                par.tags.insert("pre".to_string());
+            }
+            DocItem::Paragraph(par)
+        }).collect();
+        Doc { items, ..Default::default() }
+    }
+
+    fn render_pre(doc: &Doc, word_wrap: bool, overflow: PreOverflow) -> Vec<String> {
+        let mut conf = Config { width: 10, ..Default::default() };
+        conf.text.word_wrap = word_wrap;
+        conf.text.pre_overflow = overflow;
+        strip_ansi(&doc_to_ansi_string(doc, &conf)).lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn pre_keeps_spaces_and_blank_lines() {
+        let doc = pre_doc(&[("a   b\n\n\nc  d\n  indented", true)]);
+        for word_wrap in [false, true] {
+            assert_eq!(
+                render_pre(&doc, word_wrap, PreOverflow::Wrap),
+                ["a   b", "", "", "c  d", "  indented"],
+            );
+        }
+    }
+
+    #[test]
+    fn pre_overflow() {
+        let doc = pre_doc(&[("0123456789abcdef  x", true)]);
+        for word_wrap in [false, true] {
+            assert_eq!(render_pre(&doc, word_wrap, PreOverflow::Wrap), ["0123456789", "abcdef  x"]);
+            assert_eq!(render_pre(&doc, word_wrap, PreOverflow::Truncate), ["0123456789"]);
+        }
+    }
+
+    #[test]
+    fn other_paragraphs_still_reflow() {
+        let doc = pre_doc(&[("a   b", true), ("x   y  reflowed text", false)]);
+        // runs of spaces collapse, and long lines wrap rather than being cut
+        let lines = render_pre(&doc, false, PreOverflow::Truncate);
+        assert_eq!(lines, ["a   b", "x y reflow", "ed text"]);
+        let lines = render_pre(&doc, true, PreOverflow::Truncate);
+        assert_eq!(lines, ["a   b", "x y", "reflowed", "text"]);
+    }
+
+    #[test]
+    fn numbering() {
+        for (number, alpha, roman) in [
+            (1, "a", "i"), (4, "d", "iv"), (9, "i", "ix"), (26, "z", "xxvi"), (27, "aa", "xxvii"),
+            (28, "ab", "xxviii"), (1994, "bxr", "mcmxciv"),
+        ] {
+            assert_eq!(Numbering::LowerAlpha.format(number), alpha);
+            assert_eq!(Numbering::UpperAlpha.format(number), alpha.to_uppercase());
+            assert_eq!(Numbering::LowerRoman.format(number), roman);
+            assert_eq!(Numbering::UpperRoman.format(number), roman.to_uppercase());
+        }
+        assert_eq!(Numbering::Decimal.format(12), "12");
+    }
+
+    #[test]
+    fn list_markers_by_depth() {
+        let md = "1. a\n   1. b\n      1. c\n         1. d\n\n- e\n  - f\n    - g\n      - h";
+        assert_eq!(
+            render(md, 40, false),
+            [
+                "1. a", "   a. b", "      i. c", "         1. d",
+                "• e", "  ◦ f", "    ▪ g", "      • h",
+            ],
+        );
+        let mut conf = Config { width: 40, ..Default::default() };
+        conf.list.numbering = vec![Numbering::UpperRoman, Numbering::UpperAlpha];
+        conf.list.bullets = vec!["-".to_string(), "+".to_string()];
+        assert_eq!(
+            render_with("1. a\n   1. b\n      1. c\n\n- d\n  - e\n    - f", &conf),
+            ["I. a", "   A. b", "      I. c", "- d", "  + e", "    - f"],
+        );
+    }
+
+    #[test]
+    fn list_start_and_marker_alignment() {
+        let md = (0..3).map(|i| format!("{}. item\n", i + 1)).collect::<String>();
+        let mut conf = Config { width: 40, ..Default::default() };
+        conf.list.start = 9;
+        assert_eq!(render_with(&md, &conf), [" 9. item", "10. item", "11. item"]);
+        conf.list.marker_align = Align::Left;
+        assert_eq!(render_with(&md, &conf), ["9.  item", "10. item", "11. item"]);
+        conf.list.numbering = vec![Numbering::LowerRoman];
+        conf.list.start = 1;
+        assert_eq!(render_with(&md, &conf), ["i.   item", "ii.  item", "iii. item"]);
+        conf.list.marker_align = Align::Right;
+        assert_eq!(render_with(&md, &conf), ["  i. item", " ii. item", "iii. item"]);
+    }
+
+    #[test]
+    fn marker_colours() {
+        let mut conf = Config { width: 40, ..Default::default() };
+        conf.list.marker_style = Style { fg: Some(config::Colour::Red), ..Default::default() };
+        let doc = parse_input("- item", Format::Markdown).expect("markdown always parses");
+        let output = doc_to_ansi_string(&doc, &conf);
+        assert!(output.contains(&format!("{RED}•{RESET}")), "{output:?}");
+        assert_eq!(strip_ansi(&output).trim_end(), "• item");
+    }
+
+    /// A list of which each item is a paragraph of the items.
+    fn list_of(items: Vec<Vec<ParagraphItem>>) -> List {
+        List {
+            ltype: ListType::Identical,
+            items: items.into_iter()
+                .map(|items| Paragraph { items, ..Default::default() })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn text(text: &str) -> ParagraphItem {
+        ParagraphItem::Text(text.to_string())
+    }
+
+    #[test]
+    fn loose_and_tight_lists() {
+        let code = || ParagraphItem::Code(Ok(CodeBlock {
+            language: "rust".to_string(),
+            code: "x".to_string(),
+            ..Default::default()
+        }));
+        let nested = || ParagraphItem::List(list_of(vec![vec![text("b")]]));
+        assert!(!list_is_loose(&list_of(vec![vec![text("a")], vec![text("b")]])));
+        assert!(!list_is_loose(&list_of(vec![vec![text("a\nb")], vec![text("c")]])));
+        assert!(!list_is_loose(&list_of(vec![vec![text("a\n\n")], vec![text("\n\nc")]])));
+        assert!(!list_is_loose(&list_of(vec![vec![text("a"), nested(), text(" ")]])));
+        assert!(list_is_loose(&list_of(vec![vec![text("a")], vec![text("b\n\nc")]])));
+        assert!(list_is_loose(&list_of(vec![vec![text("a\n\n"), text("b")]])));
+        assert!(list_is_loose(&list_of(vec![vec![text("a"), code()]])));
+        assert!(list_is_loose(&list_of(vec![vec![text("a"), nested(), text("c")]])));
+    }
+
+    #[test]
+    fn loose_lists_are_spaced() {
+        let render_list = |list: List| {
+            let par = Paragraph { items: vec![ParagraphItem::List(list)], ..Default::default() };
+            let doc = Doc { items: vec![DocItem::Paragraph(par)], ..Default::default() };
+            let output = doc_to_ansi_string(&doc, &Config { width: 40, ..Default::default() });
+            strip_ansi(&output).lines().map(|line| line.trim_end().to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(render_list(list_of(vec![vec![text("a\nb")], vec![text("c")]])), [
+            "• a", "  b", "• c",
+        ]);
+        assert_eq!(render_list(list_of(vec![vec![text("a\n\nb")], vec![text("c")]])), [
+            "• a", "  b", "", "• c",
+        ]);
+        let lines = render("- a\n\n  ```rust\n  x\n  ```\n- c", 40, false);
+        let lines: Vec<&str> = lines.iter().map(|line| line.trim_end()).collect();
+        assert_eq!(lines[0], "• a");
+        assert_eq!(lines[lines.len() - 2..], ["", "• c"]);
+    }
+
+    #[test]
+    fn hanging_indent() {
+        let mut conf = Config { width: 16, ..Default::default() };
+        conf.text.word_wrap = true;
+        conf.list.hanging_indent = 2;
+        assert_eq!(
+            render_with("- one two three four five six\n- c\n  - nested words", &conf),
+            [
+                "•   one two", "    three four", "    five six",
+                "•   c", "    ◦   nested", "        words",
+            ],
+        );
+        conf.list.hanging_indent = 1;
+        assert_eq!(
+            render_with("1. one two three four five six", &conf),
+            ["1.  one two", "    three four", "    five six"],
+        );
+        conf.list.hanging_indent = 0;
+        assert_eq!(
+            render_with("- one two three four five six", &conf),
+            ["• one two three", "  four five six"],
+        );
+    }
+
+    /// Navigation three levels deep.
+    fn deep_nav_doc() -> Doc {
+        let link = |text: &str| Link {
+            url: format!("#{text}"),
+            items: vec![LinkItem::String(text.to_string())],
+            ..Default::default()
+        };
+        let nav = |description: &str, links: &[&str], subs: Vec<Nav>| Nav {
+            description: description.to_string(),
+            links: links.iter().map(|text| link(text)).collect(),
+            subs,
+            ..Default::default()
+        };
+        let advanced = nav("Advanced", &["tuning"], Vec::new());
+        let guide = nav("Guide", &["install"], vec![advanced]);
+        let reference = nav("Reference", &["api"], Vec::new());
+        let contents = nav("Contents", &["intro", "usage"], vec![guide, reference]);
+        Doc { items: vec![DocItem::Nav(contents)], ..Default::default() }
+    }
+
+    fn render_nav(style: NavStyle) -> Vec<String> {
+        let mut conf = Config { width: 40, ..Default::default() };
+        conf.nav.style = style;
+        let output = doc_to_ansi_string(&deep_nav_doc(), &conf);
+        strip_ansi(&output).lines().map(|line| line.trim_end().to_string()).collect()
+    }
+
+    #[test]
+    fn nav_tree_connectors() {
+        assert_eq!(render_nav(NavStyle::Tree), [
+            "Contents",
+            "├── intro",
+            "├── usage",
+            "├── Guide",
+            "│   ├── install",
+            "│   └── Advanced",
+            "│       └── tuning",
+            "└── Reference",
+            "    └── api",
+        ]);
+    }
+
+    #[test]
+    fn nav_breadcrumbs() {
+        assert_eq!(render_nav(NavStyle::Breadcrumb), [
+            "Contents › intro › usage",
+            "   Guide › install",
+            "      Advanced › tuning",
+            "   Reference › api",
+        ]);
+    }
+
+    #[test]
+    fn presets_differ_in_the_output() {
+        let md = "# Title\n\nFirst.\n\nx\ty";
+        let preset = |conf: Config| Config { width: 40, ..conf };
+        // indentation of the section body, and tab stops
+        assert_eq!(render_with(md, &preset(Config::compact())), ["Title", " First.", " x y"]);
+        assert_eq!(render_with(md, &preset(Config::readable())), ["Title", "  First.", "  x   y"]);
+        // and empty lines between the heading and paragraphs
+        assert_eq!(
+            render_with(md, &preset(Config::spacious())),
+            ["Title", "", "    First.", "", "    x   y"],
+        );
+        // navigation as a list or as a tree
+        let nav = |conf: Config| strip_ansi(&doc_to_ansi_string(&deep_nav_doc(), &preset(conf)));
+        assert!(!nav(Config::compact()).contains("├── "));
+        assert!(nav(Config::readable()).contains("├── intro"));
+        assert!(nav(Config::spacious()).contains("├── intro"));
+    }
+}
@@ -0,0 +1,209 @@
+use icu_properties::CodePointMapData;
+use icu_properties::props::{ EastAsianWidth, GeneralCategory, LineBreak };
+
+/// Characters of Chinese and Japanese, which don't put spaces between words.
+pub fn is_cjk(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{2E80}'..='\u{2FDF}' // radicals
+            | '\u{3000}'..='\u{303F}' // symbols and punctuation
+            | '\u{3040}'..='\u{30FF}' // hiragana and katakana
+            | '\u{3100}'..='\u{31FF}' // bopomofo, kana extensions
+            | '\u{3400}'..='\u{4DBF}' // extension a
+            | '\u{4E00}'..='\u{9FFF}' // unified ideographs
+            | '\u{F900}'..='\u{FAFF}' // compatibility ideographs
+            | '\u{FF00}'..='\u{FFEF}' // halfwidth and fullwidth forms
+            | '\u{20000}'..='\u{3FFFF}' // supplementary ideographs
+    )
+}
+
+/// Whether fragments of text are separated by a space where they meet.
+pub fn space_between(before: char, after: char) -> bool {
+    !(is_cjk(before) && is_cjk(after))
+}
+
+/// Line breaking class of a character, resolved by LB1.
+fn class(ch: char) -> LineBreak {
+    match CodePointMapData::<LineBreak>::new().get(ch) {
+        LineBreak::AI | LineBreak::SG | LineBreak::XX => LineBreak::AL,
+        LineBreak::SA if matches!(
+            CodePointMapData::<GeneralCategory>::new().get(ch),
+            GeneralCategory::NonspacingMark | GeneralCategory::SpacingMark
+        ) => LineBreak::CM,
+        LineBreak::SA => LineBreak::AL,
+        LineBreak::CJ => LineBreak::NS,
+        class => class,
+    }
+}
+
+/// Whether the character is fullwidth, wide or halfwidth in East Asian typography.
+fn is_east_asian(ch: char) -> bool {
+    matches!(
+        CodePointMapData::<EastAsianWidth>::new().get(ch),
+        EastAsianWidth::Fullwidth | EastAsianWidth::Wide | EastAsianWidth::Halfwidth
+    )
+}
+
+/// After how many characters the text may be broken, by the rules of UAX #14.
+pub fn break_points(text: &str) -> Vec<usize> {
+    let chars: Vec<char> = text.chars().collect();
+    let original: Vec<LineBreak> = chars.iter().map(|ch| class(*ch)).collect();
+    // LB9, LB10: combining marks are part of the character before them, or else letters
+    let mut classes = original.clone();
+    let mut attached = vec![false; chars.len()];
+    for i in 0..chars.len() {
+        if matches!(original[i], LineBreak::CM | LineBreak::ZWJ) {
+            if i > 0 && !matches!(
+                classes[i - 1],
+                LineBreak::BK | LineBreak::CR | LineBreak::LF | LineBreak::NL | LineBreak::SP
+                    | LineBreak::ZW
+            ) {
+                classes[i] = classes[i - 1];
+                attached[i] = true;
+            } else {
+                classes[i] = LineBreak::AL;
+            }
+        }
+    }
+    (1..chars.len())
+        .filter(|at| !attached[*at] && original[at - 1] != LineBreak::ZWJ)
+        .filter(|at| breaks(&chars, &classes, *at))
+        .collect()
+}
+
+/// Whether a line may be broken before the character at `at`, by LB4 to LB31.
+fn breaks(chars: &[char], classes: &[LineBreak], at: usize) -> bool {
+    use LineBreak as Lb;
+    let (before, after) = (classes[at - 1], classes[at]);
+    // the class before any spaces in front of the character
+    let spaced = classes[..at].iter().rev().find(|class| **class != Lb::SP).copied();
+    let is = |class: LineBreak, of: &[LineBreak]| of.contains(&class);
+    let letter = [Lb::AL, Lb::HL];
+    let jamo = [Lb::JL, Lb::JV, Lb::JT, Lb::H2, Lb::H3];
+    match () {
+        // LB4, LB5
+        _ if before == Lb::CR => after != Lb::LF,
+        _ if is(before, &[Lb::BK, Lb::LF, Lb::NL]) => true,
+        // LB6, LB7
+        _ if is(after, &[Lb::BK, Lb::CR, Lb::LF, Lb::NL, Lb::SP, Lb::ZW]) => false,
+        // LB8
+        _ if spaced == Some(Lb::ZW) => true,
+        // LB11, LB12, LB12a
+        _ if after == Lb::WJ || before == Lb::WJ || before == Lb::GL => false,
+        _ if after == Lb::GL && !is(before, &[Lb::SP, Lb::BA, Lb::HY, Lb::HH]) => false,
+        // LB13
+        _ if is(after, &[Lb::CL, Lb::CP, Lb::EX, Lb::IS, Lb::SY]) => false,
+        // LB14 to LB17
+        _ if spaced == Some(Lb::OP) => false,
+        _ if spaced == Some(Lb::QU) && after == Lb::OP => false,
+        _ if matches!(spaced, Some(Lb::CL | Lb::CP)) && after == Lb::NS => false,
+        _ if spaced == Some(Lb::B2) && after == Lb::B2 => false,
+        // LB18
+        _ if before == Lb::SP => true,
+        // LB19, LB20
+        _ if after == Lb::QU || before == Lb::QU => false,
+        _ if after == Lb::CB || before == Lb::CB => true,
+        // LB21, LB21a, LB21b, LB22
+        _ if is(after, &[Lb::BA, Lb::HY, Lb::HH, Lb::NS, Lb::IN]) || before == Lb::BB => false,
+        _ if at >= 2 && classes[at - 2] == Lb::HL && is(before, &[Lb::HY, Lb::HH, Lb::BA]) => {
+            false
+        },
+        _ if before == Lb::SY && after == Lb::HL => false,
+        // LB23, LB23a, LB24
+        _ if is(before, &letter) && after == Lb::NU || before == Lb::NU && is(after, &letter) => {
+            false
+        },
+        _ if before == Lb::PR && is(after, &[Lb::ID, Lb::EB, Lb::EM]) => false,
+        _ if is(before, &[Lb::ID, Lb::EB, Lb::EM]) && after == Lb::PO => false,
+        _ if is(before, &[Lb::PR, Lb::PO]) && is(after, &letter) => false,
+        _ if is(before, &letter) && is(after, &[Lb::PR, Lb::PO]) => false,
+        // LB25
+        _ if is(before, &[Lb::CL, Lb::CP, Lb::NU]) && is(after, &[Lb::PO, Lb::PR]) => false,
+        _ if is(before, &[Lb::PO, Lb::PR]) && after == Lb::OP => false,
+        _ if is(before, &[Lb::PO, Lb::PR, Lb::HY, Lb::IS, Lb::NU, Lb::SY]) && after == Lb::NU => {
+            false
+        },
+        // LB26, LB27
+        _ if before == Lb::JL && is(after, &[Lb::JL, Lb::JV, Lb::H2, Lb::H3]) => false,
+        _ if is(before, &[Lb::JV, Lb::H2]) && is(after, &[Lb::JV, Lb::JT]) => false,
+        _ if is(before, &[Lb::JT, Lb::H3]) && after == Lb::JT => false,
+        _ if is(before, &jamo) && after == Lb::PO || before == Lb::PR && is(after, &jamo) => false,
+        // LB28, LB28a
+        _ if is(before, &letter) && is(after, &letter) => false,
+        _ if before == Lb::AP && (is(after, &[Lb::AK, Lb::AS]) || chars[at] == '\u{25CC}') => false,
+        _ if (is(before, &[Lb::AK, Lb::AS]) || chars[at - 1] == '\u{25CC}')
+            && is(after, &[Lb::VF, Lb::VI]) => false,
+        // LB29, LB30
+        _ if before == Lb::IS && is(after, &letter) => false,
+        _ if is(before, &[Lb::AL, Lb::HL, Lb::NU]) && after == Lb::OP
+            && !is_east_asian(chars[at]) => false,
+        _ if before == Lb::CP && !is_east_asian(chars[at - 1])
+            && is(after, &[Lb::AL, Lb::HL, Lb::NU]) => false,
+        // LB30a: regional indicators pair up into flags
+        _ if before == Lb::RI && after == Lb::RI => {
+            classes[..at].iter().rev().take_while(|class| **class == Lb::RI).count() % 2 == 0
+        },
+        // LB30b, LB31
+        _ if before == Lb::EB && after == Lb::EM => false,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pieces(text: &str) -> Vec<String> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut points = break_points(text);
+        points.push(chars.len());
+        let mut start = 0;
+        points.into_iter().map(|end| {
+            let piece = chars[start..end].iter().collect();
+            start = end;
+            piece
+        }).collect()
+    }
+
+    #[test]
+    fn hangul_is_spaced() {
+        assert!(!is_cjk('한'));
+        assert!(space_between('요', '세'));
+        assert!(!space_between('日', '本'));
+        assert!(space_between('日', 'a'));
+    }
+
+    #[test]
+    fn kinsoku() {
+        assert_eq!(
+            pieces("晴れ。「雨」でしょう！"),
+            ["晴", "れ。", "「雨」", "で", "しょ", "う！"],
+        );
+        assert_eq!(pieces("コーヒー、ください"), ["コー", "ヒー、", "く", "だ", "さ", "い"]);
+        assert_eq!(pieces("（注）"), ["（注）"]);
+    }
+
+    #[test]
+    fn mixed_scripts() {
+        assert_eq!(
+            pieces("日本語とEnglishの混在"),
+            ["日", "本", "語", "と", "English", "の", "混", "在"],
+        );
+        assert_eq!(pieces("中文(English)。"), ["中", "文", "(English)。"]);
+        assert_eq!(pieces("价格¥100元"), ["价", "格", "¥100", "元"]);
+    }
+
+    #[test]
+    fn latin() {
+        assert_eq!(pieces("well-known"), ["well-", "known"]);
+        assert_eq!(pieces("-5 3.14 e.g. (a)"), ["-5 ", "3.14 ", "e.g. ", "(a)"]);
+        assert_eq!(pieces("a/b"), ["a/", "b"]);
+        assert_eq!(pieces("naïve"), ["naïve"]);
+        assert_eq!(pieces("e\u{301}te\u{301}"), ["e\u{301}te\u{301}"]);
+    }
+
+    #[test]
+    fn hangul() {
+        assert_eq!(pieces("안녕 세계"), ["안", "녕 ", "세", "계"]);
+    }
+}
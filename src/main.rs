@@ -0,0 +1,322 @@
+use incodoc_to_ansi::*;
+use incodoc_to_ansi::config::*;
+use incodoc_to_ansi::input::{ Format, parse_input };
+use incodoc_to_ansi::source_map::*;
+
+use incodoc::*;
+
+use clap::{ Arg, ArgAction, ArgMatches, Command, value_parser };
+
+use std::{
+    env,
+    fs,
+    io::{ self, IsTerminal, Read, Write },
+    process,
+};
+
+/// Narrowest width that is rendered at; tables and code need some room.
+const MIN_WIDTH: u16 = 20;
+
+/// When to use colours and effects; not to be confused with `config::Colour`.
+#[derive(Clone, Copy, Default, Debug, Eq, PartialEq)]
+enum ColourWhen {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Default, Debug)]
+struct Args {
+    input: Option<String>,
+    output: Option<String>,
+    format: Option<Format>,
+    width: Option<usize>,
+    section: Option<String>,
+    preset: Option<String>,
+    config: Option<String>,
+    no_config: bool,
+    toc: bool,
+    colour: ColourWhen,
+    colour_level: Option<ColourLevel>,
+    theme: Option<String>,
+}
+
+fn command() -> Command {
+    Command::new("incodoc-to-ansi")
+        .about("Render an incodoc or markdown document for the terminal.")
+        .arg(Arg::new("input")
+            .value_name("FILE")
+            .help("document to render; standard input when missing or `-`"))
+        .arg(Arg::new("output")
+            .short('o')
+            .long("output")
+            .value_name("FILE")
+            .help("write to FILE instead of standard output"))
+        .arg(Arg::new("from")
+            .short('f')
+            .long("from")
+            .value_name("FORMAT")
+            .value_parser(|name: &str| Format::from_name(name)
+                .ok_or(format!("unknown input format: {name}")))
+            .help("input format: incodoc or markdown [default: from the file extension]"))
+        .arg(Arg::new("width")
+            .short('w')
+            .long("width")
+            .value_name("N")
+            .value_parser(value_parser!(u16).range(i64::from(MIN_WIDTH)..))
+            .help("width to render at [default: terminal width, or 80]"))
+        .arg(Arg::new("section")
+            .short('s')
+            .long("section")
+            .value_name("ID")
+            .help("only render the section with this id or heading"))
+        .arg(Arg::new("preset")
+            .short('p')
+            .long("preset")
+            .value_name("NAME")
+            .value_parser(["compact", "readable", "spacious"])
+            .help("base configuration [default: readable]"))
+        .arg(Arg::new("config")
+            .short('c')
+            .long("config")
+            .value_name("FILE")
+            .help("configuration file [default: $XDG_CONFIG_HOME/incodoc-to-ansi/config.toml]"))
+        .arg(Arg::new("no-config")
+            .long("no-config")
+            .action(ArgAction::SetTrue)
+            .help("do not load a configuration file"))
+        .arg(Arg::new("toc")
+            .long("toc")
+            .action(ArgAction::SetTrue)
+            .help("start with a table of contents"))
+        .arg(Arg::new("theme")
+            .short('t')
+            .long("theme")
+            .value_name("NAME")
+            .help("bat theme code is highlighted with [default: ansi]"))
+        .arg(Arg::new("colour")
+            .long("colour")
+            .visible_alias("color")
+            .value_name("WHEN")
+            .value_parser(["auto", "always", "never"])
+            .default_value("auto")
+            .help("use colours and effects"))
+        .arg(Arg::new("colour-level")
+            .long("colour-level")
+            .visible_alias("color-level")
+            .value_name("LEVEL")
+            .value_parser(["basic", "ansi256", "true-colour"])
+            .help("colours the terminal can show [default: true-colour]"))
+        .arg(Arg::new("plain")
+            .long("plain")
+            .action(ArgAction::SetTrue)
+            .help("no colours or effects, same as --colour never"))
+}
+
+fn parse_args() -> Args {
+    args_from(command().get_matches())
+}
+
+fn args_from(matches: ArgMatches) -> Args {
+    let string = |id: &str| matches.get_one::<String>(id).cloned();
+    let colour = match matches.get_one::<String>("colour").map(String::as_str) {
+        _ if matches.get_flag("plain") => ColourWhen::Never,
+        Some("always") => ColourWhen::Always,
+        Some("never") => ColourWhen::Never,
+        _ => ColourWhen::Auto,
+    };
+    let colour_level = match matches.get_one::<String>("colour-level").map(String::as_str) {
+        Some("basic") => Some(ColourLevel::Basic),
+        Some("ansi256") => Some(ColourLevel::Ansi256),
+        Some(_) => Some(ColourLevel::TrueColour),
+        None => None,
+    };
+    Args {
+        input: string("input").filter(|input| input != "-"),
+        output: string("output"),
+        format: matches.get_one::<Format>("from").copied(),
+        width: matches.get_one::<u16>("width").map(|width| usize::from(*width)),
+        section: string("section"),
+        preset: string("preset"),
+        config: string("config"),
+        no_config: matches.get_flag("no-config"),
+        toc: matches.get_flag("toc"),
+        colour,
+        colour_level,
+        theme: string("theme"),
+    }
+}
+
+#[cfg(feature = "serde")]
+fn load_config(path: Option<&str>, base: &Config) -> Result<Config, String> {
+    match path {
+        Some(path) => Config::load(std::path::Path::new(path), base),
+        None => Config::load_default(base),
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn load_config(path: Option<&str>, base: &Config) -> Result<Config, String> {
+    match path {
+        Some(_) => Err("built without configuration file support (feature `serde`)".to_string()),
+        None => Ok(base.clone()),
+    }
+}
+
+/// Table of contents: a nested list linking to the sections of the document.
+fn toc(doc: &Doc) -> Paragraph {
+    let sections: Vec<&Section> = doc.items.iter().filter_map(|item| match item {
+        DocItem::Section(section) => Some(section),
+        _ => None,
+    }).collect();
+    Paragraph {
+        items: vec![ParagraphItem::List(toc_list(&sections))],
+        ..Default::default()
+    }
+}
+
+fn toc_list(sections: &[&Section]) -> List {
+    let mut list = List::default();
+    for section in sections {
+        if section.tags.contains("blockquote") || section.tags.contains("blockquote-typed") {
+            continue;
+        }
+        let heading = heading_text(&section.heading);
+        let mut item = Paragraph::default();
+        item.items.push(ParagraphItem::Link(Link {
+            url: format!("#{}", slug(&heading)),
+            items: vec![LinkItem::String(heading)],
+            ..Default::default()
+        }));
+        let subs: Vec<&Section> = section.items.iter().filter_map(|item| match item {
+            SectionItem::Section(section) => Some(section),
+            _ => None,
+        }).collect();
+        let subs = toc_list(&subs);
+        if !subs.items.is_empty() {
+            item.items.push(ParagraphItem::List(subs));
+        }
+        list.items.push(item);
+    }
+    list
+}
+
+/// The preset, with the configuration file on top of it, and the options on top of both.
+fn configure(args: &Args) -> Result<Config, String> {
+    let mut conf = match &args.preset {
+        Some(name) => Config::preset(name).ok_or(format!("unknown preset: {name}"))?,
+        None => Config::readable(),
+    };
+    if !args.no_config {
+        conf = load_config(args.config.as_deref(), &conf)?;
+    }
+    if let Some(width) = args.width {
+        conf.width = width;
+    }
+    if let Some(theme) = &args.theme {
+        conf.code_block.theme = theme.clone();
+    }
+    if let Some(level) = args.colour_level {
+        conf.colour_level = level;
+    }
+    conf.validate()?;
+    Ok(conf)
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let (input, format) = match &args.input {
+        Some(path) => (
+            fs::read_to_string(path).map_err(|e| format!("could not read {path}: {e}"))?,
+            args.format.unwrap_or(Format::from_path(path)),
+        ),
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)
+                .map_err(|e| format!("could not read standard input: {e}"))?;
+            (input, args.format.unwrap_or_default())
+        },
+    };
+    let mut doc = parse_input(&input, format)?;
+
+    if let Some(id) = &args.section {
+        let url = format!("#{}", id.trim_start_matches('#'));
+        let section = find_section(&doc, &url)
+            .and_then(|path| match node_at_path(&doc, &path) {
+                Some(Node::Section(section)) => Some(section.clone()),
+                _ => None,
+            })
+            .ok_or(format!("section not found: {id}"))?;
+        doc.items = vec![DocItem::Section(section)];
+    }
+    if args.toc {
+        let toc = toc(&doc);
+        doc.items.insert(0, DocItem::Paragraph(toc));
+    }
+
+    let conf = configure(&args)?;
+    let mut output = doc_to_ansi_string(&doc, &conf);
+    let colour = match args.colour {
+        ColourWhen::Auto => env::var_os("NO_COLOR").is_none()
+            && args.output.is_none() && io::stdout().is_terminal(),
+        ColourWhen::Always => true,
+        ColourWhen::Never => false,
+    };
+    if !colour {
+        output = strip_ansi(&output);
+    } else if args.output.is_none() && let Some(title) = header::window_title(&doc, &conf) {
+        output.insert_str(0, &title);
+    }
+    output += "\n";
+
+    match &args.output {
+        Some(path) => fs::write(path, output).map_err(|e| format!("could not write {path}: {e}")),
+        None => io::stdout().write_all(output.as_bytes())
+            .map_err(|e| format!("could not write to standard output: {e}")),
+    }
+}
+
+fn main() {
+    if let Err(e) = run(parse_args()) {
+        eprintln!("error: {e}");
+        process::exit(1);
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    fn config_from(args: &[&str]) -> Result<Config, String> {
+        let matches = command().try_get_matches_from(["incodoc-to-ansi"].iter().chain(args))
+            .map_err(|e| e.to_string())?;
+        configure(&args_from(matches))
+    }
+
+    #[test]
+    fn options_go_over_the_file_over_the_preset() {
+        let path = env::temp_dir().join(format!("incodoc-to-ansi-{}.toml", process::id()));
+        let toml = "width = 33\ntab_width = 3\n[section]\nparagraph_indent = 7\n";
+        fs::write(&path, toml).expect("temporary file can be written");
+        let file = path.to_str().expect("temporary path is unicode");
+
+        let conf = config_from(&["--preset", "spacious", "--config", file, "--width", "50"]);
+        let conf = conf.expect("valid configuration");
+        let spacious = Config::spacious();
+        assert_eq!(conf.width, 50);
+        assert_eq!(conf.tab_width, 3);
+        assert_eq!(conf.section.paragraph_indent, 7);
+        assert_eq!(conf.section.section_indent, spacious.section.section_indent);
+        assert_eq!(conf.headed_section, spacious.headed_section);
+        assert_eq!(conf.nav, spacious.nav);
+
+        // without a preset the file goes over the readable one
+        let conf = config_from(&["--config", file]).expect("valid configuration");
+        assert_eq!((conf.width, conf.section.paragraph_indent), (33, 7));
+        assert_eq!(conf.nav, Config::readable().nav);
+
+        let conf = config_from(&["--preset", "compact", "--config", file, "--no-config"]);
+        assert_eq!(conf.map(|conf| conf.tab_width), Ok(Config::compact().tab_width));
+        fs::remove_file(&path).expect("temporary file can be removed");
+    }
+}
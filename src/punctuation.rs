@@ -0,0 +1,80 @@
+use crate::config::{ PunctuationRule, Spacing, TextConfig };
+
+/// Punctuation rules for the language of the text: those of the language, of the language without
+/// its region, or the default ones.
+pub fn rules(text: &TextConfig) -> &[PunctuationRule] {
+    let base = text.language.split(['-', '_']).next().unwrap_or_default();
+    [text.language.as_str(), base, "default"].into_iter()
+        .find_map(|language| text.punctuation.get(language))
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+pub fn spacing(ch: char, text: &TextConfig) -> Option<Spacing> {
+    rules(text).iter().find(|rule| rule.chars.contains(ch)).map(|rule| rule.spacing)
+}
+
+/// Whether text starting with this is not to be separated from what comes before it.
+pub fn joins_previous(next: &str, text: &TextConfig) -> bool {
+    let mut chars = next.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+    match spacing(first, text) {
+        Some(Spacing::NoSpaceBefore | Spacing::NbspBefore) => true,
+        // a quote that isn't followed by a word is closing
+        Some(Spacing::Quote) => !chars.next().is_some_and(char::is_alphanumeric),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ doc_to_ansi_string, strip_ansi };
+    use crate::config::Config;
+    use crate::input::{ Format, parse_input };
+
+    fn text(language: &str) -> TextConfig {
+        TextConfig { language: language.to_string(), ..Default::default() }
+    }
+
+    fn render(md: &str, language: &str) -> String {
+        let doc = parse_input(md, Format::Markdown).expect("markdown always parses");
+        let mut conf = Config { width: 80, ..Config::compact() };
+        conf.text.language = language.to_string();
+        strip_ansi(&doc_to_ansi_string(&doc, &conf)).trim().to_string()
+    }
+
+    #[test]
+    fn rules_by_language() {
+        assert_eq!(spacing('!', &text("fr-CA")), Some(Spacing::NbspBefore));
+        assert_eq!(spacing('\u{201E}', &text("de_AT")), Some(Spacing::NoSpaceAfter));
+        assert_eq!(spacing('!', &text("nl")), Some(Spacing::NoSpaceBefore));
+        assert_eq!(spacing('a', &text("nl")), None);
+        let mut none = text("en");
+        none.punctuation.clear();
+        assert!(rules(&none).is_empty());
+    }
+
+    #[test]
+    fn joins() {
+        let en = text("en");
+        assert!(joins_previous(", and", &en));
+        assert!(joins_previous("\" he said", &en));
+        assert!(!joins_previous("\"quoted", &en));
+        assert!(!joins_previous("(aside)", &en));
+        assert!(!joins_previous("", &en));
+        assert!(joins_previous("? ", &text("fr")));
+    }
+
+    #[test]
+    fn spaces_punctuation() {
+        assert_eq!(render("Hello , world ( really ) !", "en"), "Hello, world (really)!");
+        assert_eq!(render("He said \u{201C} hi \u{201D} .", "en"), "He said \u{201C}hi\u{201D}.");
+        assert_eq!(
+            render("Bonjour ! « salut » , dit-il:oui", "fr"),
+            "Bonjour\u{A0}! «\u{A0}salut\u{A0}», dit-il\u{A0}:oui",
+        );
+    }
+}
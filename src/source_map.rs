@@ -0,0 +1,317 @@
+use incodoc::*;
+
+use crate::ParStatus;
+
+/// Path to a node: the index taken at each level, starting from `Doc::items`.
+/// Inside a `Nav` the links come first, followed by the subs.
+pub type NodePath = Vec<usize>;
+
+/// Kind of the element a span was rendered from.
+#[derive(Clone, Copy, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum NodeKind {
+    Nav,
+    NavLink,
+    Section,
+    Blockquote,
+    Heading,
+    Paragraph,
+    Text,
+    InlineCode,
+    Emphasis,
+    /// strongly deemphasised text
+    Spoiler,
+    Link,
+    CodeBlock,
+    List,
+    ListItem,
+    Table,
+}
+
+/// Output position: (row, col), both starting at 0.
+pub type Position = (usize, usize);
+
+/// Region of the output, in reading order, that was rendered from a single node.
+/// The start is inclusive and the end is exclusive.
+#[derive(Clone, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+    pub path: NodePath,
+    pub kind: NodeKind,
+}
+
+impl Span {
+    pub fn contains(&self, pos: Position) -> bool {
+        self.start <= pos && pos < self.end
+    }
+}
+
+/// Spans of the output mapped to the nodes they were rendered from.
+#[derive(Clone, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct SourceMap {
+    pub spans: Vec<Span>,
+    /// checked lists, so they can be rendered again alone
+    pub checklists: Vec<Checklist>,
+}
+
+impl SourceMap {
+    /// Find the innermost node rendered at the given output position.
+    pub fn node_at(&self, row: usize, col: usize) -> Option<&Span> {
+        self.spans
+            .iter()
+            .filter(|span| span.contains((row, col)))
+            .max_by(|a, b| {
+                a.path.len().cmp(&b.path.len())
+                    .then(a.start.cmp(&b.start))
+                    .then(b.end.cmp(&a.end))
+            })
+    }
+
+    /// All spans rendered from the node at the given path.
+    pub fn spans_of<'a>(&'a self, path: &'a [usize]) -> impl Iterator<Item = &'a Span> {
+        self.spans.iter().filter(move |span| span.path == path)
+    }
+
+    /// Move the spans starting from index `from` by the given amount of rows and columns.
+    pub fn shift(&mut self, from: usize, rows: usize, cols: usize) {
+        for span in &mut self.spans[from..] {
+            span.start.0 += rows;
+            span.start.1 += cols;
+            span.end.0 += rows;
+            span.end.1 += cols;
+        }
+    }
+}
+
+/// Where and how a checked list was rendered: what is needed to render it again in place.
+#[derive(Clone, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Checklist {
+    pub path: NodePath,
+    /// status, position and indentation in the column the list was rendered in
+    pub ps: ParStatus,
+    pub row: usize,
+    pub col: usize,
+    pub indentation: usize,
+    pub indented: usize,
+    pub width: usize,
+    pub list_depth: usize,
+    pub fg_mod: String,
+    pub bg_mod: String,
+    /// row of the column where the first item starts
+    pub first_row: usize,
+    /// where each row of the list, from the first item on, starts in the output
+    pub rows: Vec<Position>,
+}
+
+impl Checklist {
+    /// Move the rows of the list in the output by the given amount of rows and columns.
+    pub fn shift(&mut self, rows: usize, cols: usize) {
+        for (row, col) in &mut self.rows {
+            *row += rows;
+            *col += cols;
+        }
+    }
+}
+
+/// Node of a document that can be addressed with a `NodePath`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Node<'a> {
+    Nav(&'a Nav),
+    Link(&'a Link),
+    Section(&'a Section),
+    Paragraph(&'a Paragraph),
+    Item(&'a ParagraphItem),
+}
+
+/// Find the node at the given path in the document.
+pub fn node_at_path<'a>(doc: &'a Doc, path: &[usize]) -> Option<Node<'a>> {
+    let (first, rest) = path.split_first()?;
+    let mut node = match doc.items.get(*first)? {
+        DocItem::Nav(nav) => Node::Nav(nav),
+        DocItem::Paragraph(par) => Node::Paragraph(par),
+        DocItem::Section(section) => Node::Section(section),
+    };
+    for i in rest {
+        node = match node {
+            Node::Nav(nav) if *i < nav.links.len() => Node::Link(&nav.links[*i]),
+            Node::Nav(nav) => Node::Nav(nav.subs.get(*i - nav.links.len())?),
+            Node::Section(section) => match section.items.get(*i)? {
+                SectionItem::Paragraph(par) => Node::Paragraph(par),
+                SectionItem::Section(section) => Node::Section(section),
+            },
+            Node::Paragraph(par) => Node::Item(par.items.get(*i)?),
+            Node::Item(ParagraphItem::List(list)) => Node::Paragraph(list.items.get(*i)?),
+            Node::Item(_) | Node::Link(_) => return None,
+        };
+    }
+    Some(node)
+}
+
+/// Mutable node of a document that can be addressed with a `NodePath`.
+#[derive(Debug, Eq, PartialEq)]
+pub enum NodeMut<'a> {
+    Nav(&'a mut Nav),
+    Link(&'a mut Link),
+    Section(&'a mut Section),
+    Paragraph(&'a mut Paragraph),
+    Item(&'a mut ParagraphItem),
+}
+
+/// Find the node at the given path in the document, for changing it.
+pub fn node_at_path_mut<'a>(doc: &'a mut Doc, path: &[usize]) -> Option<NodeMut<'a>> {
+    let (first, rest) = path.split_first()?;
+    let mut node = match doc.items.get_mut(*first)? {
+        DocItem::Nav(nav) => NodeMut::Nav(nav),
+        DocItem::Paragraph(par) => NodeMut::Paragraph(par),
+        DocItem::Section(section) => NodeMut::Section(section),
+    };
+    for i in rest {
+        node = match node {
+            NodeMut::Nav(nav) if *i < nav.links.len() => NodeMut::Link(&mut nav.links[*i]),
+            NodeMut::Nav(nav) => {
+                let sub = *i - nav.links.len();
+                NodeMut::Nav(nav.subs.get_mut(sub)?)
+            },
+            NodeMut::Section(section) => match section.items.get_mut(*i)? {
+                SectionItem::Paragraph(par) => NodeMut::Paragraph(par),
+                SectionItem::Section(section) => NodeMut::Section(section),
+            },
+            NodeMut::Paragraph(par) => NodeMut::Item(par.items.get_mut(*i)?),
+            NodeMut::Item(ParagraphItem::List(list)) => {
+                NodeMut::Paragraph(list.items.get_mut(*i)?)
+            },
+            NodeMut::Item(_) | NodeMut::Link(_) => return None,
+        };
+    }
+    Some(node)
+}
+
+/// Plain text of a heading, without emphasis.
+pub fn heading_text(heading: &Heading) -> String {
+    let mut res = String::new();
+    for item in &heading.items {
+        match item {
+            HeadingItem::String(string) => res += string,
+            HeadingItem::Em(em) => res += &em.text,
+        }
+    }
+    res
+}
+
+/// Find the section an internal link (`#id`) points to.
+/// It matches an `id` property of the section or heading, or the slug of the heading text.
+pub fn find_section(doc: &Doc, url: &str) -> Option<NodePath> {
+    let id = url.strip_prefix('#')?;
+    let mut path = Vec::new();
+    for (i, item) in doc.items.iter().enumerate() {
+        if let DocItem::Section(section) = item {
+            path.push(i);
+            if find_section_in(section, url, id, &mut path) {
+                return Some(path);
+            }
+            path.pop();
+        }
+    }
+    None
+}
+
+fn find_section_in(section: &Section, url: &str, id: &str, path: &mut NodePath) -> bool {
+    let is_id = |props: &Props, id: &str| {
+        matches!(props.get("id"), Some(PropVal::String(val)) if val == id)
+    };
+    if is_id(&section.props, url) || is_id(&section.props, id) || is_id(&section.heading.props, id)
+        || slug(&heading_text(&section.heading)) == id
+    {
+        return true;
+    }
+    for (i, item) in section.items.iter().enumerate() {
+        if let SectionItem::Section(sub) = item {
+            path.push(i);
+            if find_section_in(sub, url, id, path) {
+                return true;
+            }
+            path.pop();
+        }
+    }
+    false
+}
+
+/// Anchor for a heading: lowercase, words joined with dashes and without punctuation.
+pub fn slug(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|ch| match ch {
+            ' ' | '-' => Some('-'),
+            ch if ch.is_alphanumeric() || ch == '_' => Some(ch),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(kind: NodeKind, path: &[usize], start: Position, end: Position) -> Span {
+        Span { start, end, path: path.to_vec(), kind }
+    }
+
+    fn map() -> SourceMap {
+        SourceMap {
+            spans: vec![
+                span(NodeKind::Heading, &[0], (0, 0), (0, 5)),
+                span(NodeKind::Text, &[0, 0, 0], (2, 2), (2, 10)),
+                span(NodeKind::Link, &[0, 0, 1], (2, 10), (3, 4)),
+                span(NodeKind::Paragraph, &[0, 0], (2, 2), (4, 6)),
+                span(NodeKind::Section, &[0], (0, 0), (4, 6)),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn kind_at(map: &SourceMap, row: usize, col: usize) -> Option<NodeKind> {
+        map.node_at(row, col).map(|span| span.kind)
+    }
+
+    #[test]
+    fn innermost_spans_win() {
+        let map = map();
+        assert_eq!(kind_at(&map, 2, 2), Some(NodeKind::Text));
+        assert_eq!(kind_at(&map, 2, 12), Some(NodeKind::Link));
+        assert_eq!(kind_at(&map, 4, 0), Some(NodeKind::Paragraph));
+        assert_eq!(kind_at(&map, 1, 30), Some(NodeKind::Section));
+        // of spans of the same node, the one that starts last and ends first
+        assert_eq!(kind_at(&map, 0, 1), Some(NodeKind::Heading));
+    }
+
+    #[test]
+    fn spans_run_through_rows_and_end_before_their_end() {
+        let map = map();
+        // the link wraps onto the next row
+        assert_eq!(kind_at(&map, 2, 50), Some(NodeKind::Link));
+        assert_eq!(kind_at(&map, 3, 3), Some(NodeKind::Link));
+        assert_eq!(kind_at(&map, 3, 4), Some(NodeKind::Paragraph));
+        assert_eq!(kind_at(&map, 2, 9), Some(NodeKind::Text));
+        assert_eq!(kind_at(&map, 0, 5), Some(NodeKind::Section));
+    }
+
+    #[test]
+    fn nothing_outside_all_spans() {
+        let map = map();
+        assert_eq!(kind_at(&map, 4, 6), None);
+        assert_eq!(kind_at(&map, 9, 0), None);
+        assert_eq!(kind_at(&SourceMap::default(), 0, 0), None);
+    }
+
+    #[test]
+    fn spans_of_nodes_and_shifting() {
+        let mut map = map();
+        let kinds: Vec<NodeKind> = map.spans_of(&[0]).map(|span| span.kind).collect();
+        assert_eq!(kinds, [NodeKind::Heading, NodeKind::Section]);
+        map.shift(1, 10, 3);
+        assert_eq!(map.spans[0].start, (0, 0));
+        assert_eq!((map.spans[2].start, map.spans[2].end), ((12, 13), (13, 7)));
+        assert_eq!(kind_at(&map, 12, 13), Some(NodeKind::Link));
+    }
+}
@@ -0,0 +1,209 @@
+use crate::*;
+use crate::config::{ Colour, Style };
+
+impl Colour {
+    pub fn fg(self) -> &'static str {
+        match self {
+            Colour::Black => BLACK,
+            Colour::Red => RED,
+            Colour::Green => GREEN,
+            Colour::Yellow => YELLOW,
+            Colour::Blue => BLUE,
+            Colour::Magenta => MAGENTA,
+            Colour::Cyan => CYAN,
+            Colour::White => WHITE,
+            Colour::Default => DEFAULT,
+        }
+    }
+
+    pub fn bg(self) -> &'static str {
+        match self {
+            Colour::Black => BG_BLACK,
+            Colour::Red => BG_RED,
+            Colour::Green => BG_GREEN,
+            Colour::Yellow => BG_YELLOW,
+            Colour::Blue => BG_BLUE,
+            Colour::Magenta => BG_MAGENTA,
+            Colour::Cyan => BG_CYAN,
+            Colour::White => BG_WHITE,
+            Colour::Default => BG_DEFAULT,
+        }
+    }
+}
+
+/// Whether a selector like "section.warning" or ".kbd" matches an element with these tags.
+fn matches(selector: &str, element: &str, tags: &Tags) -> bool {
+    let mut parts = selector.split('.');
+    let selected = parts.next().unwrap_or_default();
+    (selected.is_empty() || selected == element) && parts.all(|tag| tags.contains(tag))
+}
+
+/// The style of an element from all rules that select it, more specific rules taking precedence.
+pub fn element_style(element: &str, tags: &Tags, conf: &Config) -> Style {
+    let mut rules: Vec<(&String, &Style)> = conf.styles.iter()
+        .filter(|(selector, _)| matches(selector, element, tags))
+        .collect();
+    rules.sort_by_key(|(selector, _)| (selector.matches('.').count(), !selector.starts_with('.')));
+    let mut res = Style::default();
+    for (_, style) in rules {
+        res.fg = style.fg.or(res.fg);
+        res.bg = style.bg.or(res.bg);
+        res.border |= style.border;
+        res.indent = res.indent.max(style.indent);
+        if !style.prefix.is_empty() {
+            res.prefix = style.prefix.clone();
+        }
+    }
+    res
+}
+
+/// The style of text in a paragraph, which has no border or indentation.
+pub fn inline_style(element: &str, tags: &Tags, conf: &Config) -> Style {
+    Style { border: false, indent: 0, ..element_style(element, tags, conf) }
+}
+
+/// Render something in a style. Gives where the box starts when it has a border.
+pub fn styled_to_ansi(
+    style: &Style,
+    conf: &Config,
+    c: &mut Context,
+    output: &mut String,
+    render: impl FnOnce(&mut Context, &mut String),
+) -> Option<Position> {
+    if let Some(fg) = style.fg {
+        c.push_fg_mod(fg.fg(), output);
+    }
+    if let Some(bg) = style.bg {
+        c.push_bg_mod(bg.bg(), output);
+    }
+    if style.indent > 0 {
+        c.push_indent(style.indent, 0);
+    }
+    // the prefix goes in front of the first text, unless there is none
+    let prefix = c.prefix.clone();
+    c.prefix += &style.prefix;
+    let start = if style.border {
+        Some(boxed_to_ansi(conf.blockquote.pre_quote_mns, c, output, render))
+    } else {
+        render(c, output);
+        None
+    };
+    if !c.prefix.is_empty() {
+        c.prefix = prefix;
+    }
+    if style.indent > 0 {
+        c.pop_indent();
+    }
+    if style.bg.is_some() {
+        c.pop_bg_mod(output);
+    }
+    if style.fg.is_some() {
+        c.pop_fg_mod(output);
+    }
+    start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{ Format, parse_input };
+    use crate::source_map::NodeKind;
+
+    fn tags(tags: &[&str]) -> Tags {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    fn with_styles(styles: &[(&str, Style)]) -> Config {
+        let mut conf = Config { width: 40, ..Default::default() };
+        for (selector, style) in styles {
+            conf.styles.insert(selector.to_string(), style.clone());
+        }
+        conf
+    }
+
+    fn render(md: &str, conf: &Config) -> (Vec<String>, SourceMap) {
+        let doc = parse_input(md, Format::Markdown).expect("markdown always parses");
+        let (output, map) = doc_to_ansi_string_with_source_map(&doc, conf);
+        (strip_ansi(&output).lines().map(String::from).collect(), map)
+    }
+
+    /// The text in the output from where the span starts.
+    fn at<'a>(lines: &'a [String], span: &Span) -> &'a str {
+        let line = &lines[span.start.0];
+        let start = line.char_indices().nth(span.start.1).map(|(i, _)| i).unwrap_or(line.len());
+        &line[start..]
+    }
+
+    #[test]
+    fn selectors() {
+        assert!(matches("section", "section", &tags(&[])));
+        assert!(matches("section.warning", "section", &tags(&["warning", "other"])));
+        assert!(!matches("section.warning", "section", &tags(&["other"])));
+        assert!(!matches("section.warning", "paragraph", &tags(&["warning"])));
+        assert!(matches(".kbd", "text", &tags(&["kbd"])));
+        assert!(matches(".a.b", "link", &tags(&["a", "b"])));
+        assert!(!matches(".a.b", "link", &tags(&["a"])));
+    }
+
+    #[test]
+    fn more_specific_rules_take_precedence() {
+        let conf = with_styles(&[
+            ("text", Style { fg: Some(Colour::Red), indent: 2, ..Default::default() }),
+            (".kbd", Style {
+                fg: Some(Colour::Green), bg: Some(Colour::Black), ..Default::default()
+            }),
+            ("text.kbd", Style { fg: Some(Colour::Blue), ..Default::default() }),
+        ]);
+        let style = element_style("text", &tags(&["kbd"]), &conf);
+        assert_eq!(style.fg, Some(Colour::Blue));
+        assert_eq!(style.bg, Some(Colour::Black));
+        assert_eq!(style.indent, 2);
+        assert_eq!(element_style("text", &tags(&[]), &conf).fg, Some(Colour::Red));
+        assert_eq!(element_style("link", &tags(&[]), &conf), Style::default());
+        // text in a paragraph can't be boxed or indented
+        let boxed = Style { border: true, indent: 2, ..Default::default() };
+        let conf = with_styles(&[("text", boxed)]);
+        assert_eq!(inline_style("text", &tags(&[]), &conf), Style::default());
+    }
+
+    #[test]
+    fn styled_output() {
+        let conf = with_styles(&[
+            ("paragraph", Style { indent: 4, prefix: "> ".to_string(), ..Default::default() }),
+            ("emphasis", Style { fg: Some(Colour::Red), ..Default::default() }),
+        ]);
+        let doc = parse_input("some *red* text", Format::Markdown).expect("markdown always parses");
+        let output = doc_to_ansi_string(&doc, &conf);
+        assert!(output.contains(&format!("{RED}{ITALIC}red")), "{output:?}");
+        assert_eq!(strip_ansi(&output).trim_end(), "    > some red text");
+    }
+
+    #[test]
+    fn bordered_paragraphs() {
+        let conf = with_styles(&[("paragraph", Style { border: true, ..Default::default() })]);
+        let (lines, map) = render("intro\n\nsome [link](https://example.org) here", &conf);
+        assert!(lines.iter().all(|line| visible_len(line) <= 40), "{lines:?}");
+        let pars: Vec<&Span> = map.spans.iter()
+            .filter(|span| span.kind == NodeKind::Paragraph)
+            .collect();
+        assert_eq!(pars.len(), 2);
+        for par in pars {
+            assert!(at(&lines, par).starts_with('┌'), "{par:?} in {lines:?}");
+        }
+        let link = map.spans.iter().find(|span| span.kind == NodeKind::Link).expect("a link");
+        assert!(at(&lines, link).starts_with("link here"), "{link:?} in {lines:?}");
+    }
+
+    #[test]
+    fn bordered_list_items() {
+        let conf = with_styles(&[("paragraph", Style { border: true, ..Default::default() })]);
+        let (lines, map) = render("- item with a [link](https://example.org)\n- two", &conf);
+        let link = map.spans.iter().find(|span| span.kind == NodeKind::Link).expect("a link");
+        assert!(at(&lines, link).starts_with("link │"), "{link:?} in {lines:?}");
+        let texts: Vec<&Span> = map.spans.iter()
+            .filter(|span| span.kind == NodeKind::Text)
+            .collect();
+        assert!(at(&lines, texts[0]).starts_with("item with"), "{texts:?} in {lines:?}");
+        assert!(at(&lines, texts[1]).starts_with("two"), "{texts:?} in {lines:?}");
+    }
+}
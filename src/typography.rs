@@ -0,0 +1,80 @@
+/// Curly quotes, en and em dashes, ellipses and the copyright sign for straight ASCII prose.
+/// `after_word` tells whether the text directly follows a word, so a quote at its start closes.
+pub fn smarten(text: &str, after_word: bool) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut res = String::with_capacity(text.len());
+    let mut i = 0;
+    // what was put out last, so a quote after a dash made of hyphens opens
+    let mut previous = after_word.then_some('a');
+    loop {
+        let opening = previous.is_none_or(|previous| {
+            previous.is_whitespace() || "([{\u{2013}\u{2014}".contains(previous)
+        });
+        let (replacement, len) = match &chars[i..] {
+            [] => break,
+            ['-', '-', '-', ..] => ('\u{2014}', 3),
+            ['-', '-', ..] => ('\u{2013}', 2),
+            ['.', '.', '.', ..] => ('\u{2026}', 3),
+            ['(', 'c' | 'C', ')', ..] => ('\u{A9}', 3),
+            ['"', ..] if opening => ('\u{201C}', 1),
+            ['"', ..] => ('\u{201D}', 1),
+            ['\'', ..] if opening => ('\u{2018}', 1),
+            ['\'', ..] => ('\u{2019}', 1),
+            [ch, ..] => (*ch, 1),
+        };
+        res.push(replacement);
+        previous = Some(replacement);
+        i += len;
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::input::{ Format, parse_input };
+    use crate::{ doc_to_ansi_string, strip_ansi };
+
+    #[test]
+    fn dashes_ellipses_and_copyright() {
+        assert_eq!(smarten("1--2", false), "1\u{2013}2");
+        assert_eq!(smarten("yes---no", false), "yes\u{2014}no");
+        assert_eq!(smarten("----", false), "\u{2014}-");
+        assert_eq!(smarten("wait...", false), "wait\u{2026}");
+        assert_eq!(smarten("(c) 2024 (C)", false), "\u{A9} 2024 \u{A9}");
+        assert_eq!(smarten("a - b", false), "a - b");
+    }
+
+    #[test]
+    fn opening_and_closing_quotes() {
+        assert_eq!(smarten("\"quoted\"", false), "\u{201C}quoted\u{201D}");
+        assert_eq!(smarten("'quoted'", false), "\u{2018}quoted\u{2019}");
+        assert_eq!(
+            smarten("say \"a\" (\"b\") --\"c\"", false),
+            "say \u{201C}a\u{201D} (\u{201C}b\u{201D}) \u{2013}\u{201C}c\u{201D}",
+        );
+        // right after a word, as after emphasis, a quote at the start closes
+        assert_eq!(smarten("\" and", true), "\u{201D} and");
+        assert_eq!(smarten("' and", true), "\u{2019} and");
+        assert_eq!(smarten("\" and", false), "\u{201C} and");
+    }
+
+    #[test]
+    fn apostrophes() {
+        assert_eq!(smarten("don't", false), "don\u{2019}t");
+        assert_eq!(smarten("the '90s", false), "the \u{2018}90s");
+        assert_eq!(smarten("dogs' bowls", false), "dogs\u{2019} bowls");
+    }
+
+    #[test]
+    fn code_is_left_alone() {
+        let mut conf = Config::default();
+        conf.text.smart_typography = true;
+        let md = "a -- b `c -- d...`\n\n```rust\nlet e = f -- g; // (c)...\n```";
+        let doc = parse_input(md, Format::Markdown).expect("markdown always parses");
+        let output = strip_ansi(&doc_to_ansi_string(&doc, &conf));
+        assert!(output.contains("a \u{2013} b c -- d..."), "{output}");
+        assert!(output.contains("let e = f -- g; // (c)..."), "{output}");
+    }
+}
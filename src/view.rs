@@ -0,0 +1,130 @@
+#![forbid(unsafe_code)]
+
+use incodoc_to_ansi::config::*;
+use incodoc_to_ansi::input::{ Format, parse_input };
+
+use incodoc::Doc;
+
+use std::{ env, process };
+
+#[cfg(unix)]
+use incodoc_to_ansi::viewer::{ Backend, Key, KeyDecoder, run };
+
+#[cfg(unix)]
+use rustix::termios::{
+    InputModes, LocalModes, OptionalActions, SpecialCodeIndex, Termios, tcgetattr, tcgetwinsize,
+    tcsetattr,
+};
+
+#[cfg(unix)]
+use std::{
+    fs::{ File, OpenOptions },
+    io::{ self, Read, Write },
+};
+
+/// The controlling terminal in raw mode; the original mode is restored when dropped.
+#[cfg(unix)]
+struct Terminal {
+    tty: File,
+    original: Termios,
+    keys: KeyDecoder,
+}
+
+#[cfg(unix)]
+impl Terminal {
+    fn open() -> io::Result<Self> {
+        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        let original = tcgetattr(&tty)?;
+        let mut raw = original.clone();
+        raw.local_modes -= LocalModes::ICANON | LocalModes::ECHO | LocalModes::ISIG
+            | LocalModes::IEXTEN;
+        raw.input_modes -= InputModes::IXON | InputModes::ICRNL;
+        // return from reads after a tenth of a second so resizes are picked up
+        raw.special_codes[SpecialCodeIndex::VMIN] = 0;
+        raw.special_codes[SpecialCodeIndex::VTIME] = 1;
+        tcsetattr(&tty, OptionalActions::Flush, &raw)?;
+        let mut terminal = Self { tty, original, keys: KeyDecoder::default() };
+        // alternate screen and hidden cursor
+        terminal.tty.write_all(b"\x1B[?1049h\x1B[?25l")?;
+        Ok(terminal)
+    }
+}
+
+#[cfg(unix)]
+impl Backend for Terminal {
+    fn size(&self) -> (usize, usize) {
+        match tcgetwinsize(&self.tty) {
+            Ok(size) if size.ws_col > 0 => (usize::from(size.ws_col), usize::from(size.ws_row)),
+            _ => (80, 24),
+        }
+    }
+
+    fn draw(&mut self, screen: &[String]) -> io::Result<()> {
+        let mut frame = String::from("\x1B[H");
+        for (i, line) in screen.iter().enumerate() {
+            if i > 0 {
+                frame += "\r\n";
+            }
+            frame += line;
+            frame += "\x1B[K";
+        }
+        self.tty.write_all(frame.as_bytes())?;
+        self.tty.flush()
+    }
+
+    fn read_keys(&mut self) -> io::Result<Vec<Key>> {
+        let mut buf = [0; 64];
+        let n = self.tty.read(&mut buf)?;
+        // nothing came in for a while, so what is kept won't be completed anymore
+        Ok(if n == 0 { self.keys.flush() } else { self.keys.decode(&buf[..n]) })
+    }
+
+    fn set_title(&mut self, title: &str) -> io::Result<()> {
+        self.tty.write_all(title.as_bytes())
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.tty.write_all(b"\x1B[?25h\x1B[?1049l");
+        let _ = tcsetattr(&self.tty, OptionalActions::Flush, &self.original);
+    }
+}
+
+fn load(path: &str) -> Result<Doc, String> {
+    let input = std::fs::read_to_string(path).map_err(|e| format!("could not read {path}: {e}"))?;
+    parse_input(&input, Format::from_path(path))
+}
+
+fn main() {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: incodoc-view FILE");
+        eprintln!("keys: j/k scroll, space/b page, g/G top/bottom, / search, n/N next/previous match,");
+        eprintln!("      tab/shift-tab focus link, enter follow link, c collapse section,");
+        eprintln!("      x check item, q quit");
+        process::exit(2);
+    };
+    let doc = load(&path).unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        process::exit(1);
+    });
+    #[cfg(feature = "serde")]
+    let conf = Config::load_default(&Config::readable()).unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        process::exit(1);
+    });
+    #[cfg(not(feature = "serde"))]
+    let conf = Config::readable();
+    #[cfg(unix)]
+    if let Err(e) = Terminal::open().and_then(|mut terminal| run(doc, conf, &mut terminal)) {
+        eprintln!("error: {e}");
+        process::exit(1);
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (doc, conf);
+        eprintln!("error: incodoc-view needs a unix terminal");
+        process::exit(1);
+    }
+}
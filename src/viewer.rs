@@ -0,0 +1,651 @@
+use crate::*;
+use crate::source_map::*;
+
+use std::collections::VecDeque;
+use std::io;
+use std::mem;
+
+/// Input for the viewer, independent of the terminal it comes from.
+#[derive(Clone, Copy, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Tab,
+    BackTab,
+    Enter,
+    Backspace,
+    Esc,
+    /// ctrl-c
+    Interrupt,
+    Char(char),
+}
+
+/// Escape sequences terminals send for keys.
+const SEQUENCES: [(&str, Key); 9] = [
+    ("\x1B[A", Key::Up), ("\x1B[B", Key::Down),
+    ("\x1B[5~", Key::PageUp), ("\x1B[6~", Key::PageDown),
+    ("\x1B[H", Key::Home), ("\x1B[F", Key::End),
+    ("\x1B[1~", Key::Home), ("\x1B[4~", Key::End),
+    ("\x1B[Z", Key::BackTab),
+];
+
+/// Turn what a terminal sends when keys are pressed into keys.
+pub fn parse_keys(input: &str) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut rest = input;
+    while let Some(ch) = rest.chars().next() {
+        let (key, len) = match ch {
+            '\x1B' => {
+                SEQUENCES.iter()
+                    .find(|(seq, _)| rest.starts_with(seq))
+                    .map(|(seq, key)| (Some(*key), seq.len()))
+                    .unwrap_or((Some(Key::Esc), 1))
+            },
+            '\t' => (Some(Key::Tab), 1),
+            '\r' | '\n' => (Some(Key::Enter), 1),
+            '\x7F' | '\x08' => (Some(Key::Backspace), 1),
+            '\x03' => (Some(Key::Interrupt), 1),
+            ch if ch.is_control() => (None, ch.len_utf8()),
+            ch => (Some(Key::Char(ch)), ch.len_utf8()),
+        };
+        keys.extend(key);
+        rest = &rest[len..];
+    }
+    keys
+}
+
+/// Keys from bytes read from a terminal in pieces. A character or escape sequence that is cut off
+/// at the end of a piece is kept until the rest of it comes in.
+#[derive(Clone, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct KeyDecoder {
+    pending: Vec<u8>,
+}
+
+impl KeyDecoder {
+    /// Keys of the bytes read, after what was kept from before.
+    pub fn decode(&mut self, bytes: &[u8]) -> Vec<Key> {
+        self.pending.extend_from_slice(bytes);
+        let mut input = String::new();
+        let mut rest = mem::take(&mut self.pending);
+        loop {
+            match std::str::from_utf8(&rest) {
+                Ok(text) => {
+                    input += text;
+                    rest.clear();
+                    break;
+                },
+                Err(e) => {
+                    let valid = e.valid_up_to();
+                    input += std::str::from_utf8(&rest[..valid]).unwrap_or_default();
+                    match e.error_len() {
+                        // bytes that can't be part of a character are replaced
+                        Some(len) => {
+                            input.push(char::REPLACEMENT_CHARACTER);
+                            rest.drain(..valid + len);
+                        },
+                        None => {
+                            rest.drain(..valid);
+                            break;
+                        },
+                    }
+                },
+            }
+        }
+        if let Some(start) = input.rfind('\x1B') {
+            let tail = &input[start..];
+            if SEQUENCES.iter().any(|(seq, _)| seq.len() > tail.len() && seq.starts_with(tail)) {
+                rest.splice(0..0, tail.bytes());
+                input.truncate(start);
+            }
+        }
+        self.pending = rest;
+        parse_keys(&input)
+    }
+
+    /// Keys of what was kept, when nothing more comes in: a lone escape is the escape key.
+    pub fn flush(&mut self) -> Vec<Key> {
+        let pending = mem::take(&mut self.pending);
+        parse_keys(&String::from_utf8_lossy(&pending))
+    }
+}
+
+/// Where the viewer is shown and gets its keys from.
+pub trait Backend {
+    /// Columns and rows.
+    fn size(&self) -> (usize, usize);
+    /// Show exactly the lines of the screen.
+    fn draw(&mut self, screen: &[String]) -> io::Result<()>;
+    /// Keys pressed since the last call. May return none after waiting a while, so that resizes
+    /// are picked up.
+    fn read_keys(&mut self) -> io::Result<Vec<Key>>;
+    fn set_title(&mut self, title: &str) -> io::Result<()>;
+}
+
+/// Screen in memory that is fed a script of keys, for running the viewer without a terminal.
+/// Reading keys fails once the script is used up.
+#[derive(Clone, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Headless {
+    pub width: usize,
+    pub height: usize,
+    pub keys: VecDeque<Key>,
+    /// last drawn screen
+    pub screen: Vec<String>,
+    pub title: String,
+}
+
+impl Headless {
+    pub fn new(width: usize, height: usize, keys: impl IntoIterator<Item = Key>) -> Self {
+        Self { width, height, keys: keys.into_iter().collect(), ..Default::default() }
+    }
+
+    /// Last drawn screen without escape sequences.
+    pub fn text(&self) -> Vec<String> {
+        self.screen.iter().map(|line| strip_ansi(line)).collect()
+    }
+}
+
+impl Backend for Headless {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn draw(&mut self, screen: &[String]) -> io::Result<()> {
+        self.screen = screen.to_vec();
+        Ok(())
+    }
+
+    fn read_keys(&mut self) -> io::Result<Vec<Key>> {
+        match self.keys.pop_front() {
+            Some(key) => Ok(vec![key]),
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "out of keys")),
+        }
+    }
+
+    fn set_title(&mut self, title: &str) -> io::Result<()> {
+        self.title = title.to_string();
+        Ok(())
+    }
+}
+
+/// Show the document on the backend until the viewer quits.
+pub fn run(doc: Doc, conf: Config, backend: &mut impl Backend) -> io::Result<Viewer> {
+    if let Some(title) = header::window_title(&doc, &conf) {
+        backend.set_title(&title)?;
+    }
+    let (width, height) = backend.size();
+    let mut viewer = Viewer::new(doc, conf, width, height);
+    backend.draw(&viewer.draw())?;
+    while !viewer.quit {
+        let keys = backend.read_keys()?;
+        let (width, height) = backend.size();
+        let resized = (width, height) != (viewer.width, viewer.height);
+        viewer.resize(width, height);
+        for key in &keys {
+            viewer.handle(*key);
+        }
+        if resized || !keys.is_empty() {
+            backend.draw(&viewer.draw())?;
+        }
+    }
+    Ok(viewer)
+}
+
+#[derive(Clone, Default, Hash, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Mode {
+    #[default]
+    Normal,
+    /// typing a search query
+    Search(String),
+}
+
+/// Scrollable view of a rendered document.
+/// Drawing produces a buffer of lines so it can be shown in any terminal or inspected headless.
+#[derive(Clone, Debug)]
+pub struct Viewer {
+    pub doc: Doc,
+    pub conf: Config,
+    pub state: RenderState,
+    pub lines: Vec<String>,
+    pub plain_lines: Vec<String>,
+    pub map: SourceMap,
+    /// what can be focused: links, and spoilers that are revealed when focused
+    pub links: Vec<Span>,
+    pub scroll: usize,
+    pub width: usize,
+    pub height: usize,
+    pub mode: Mode,
+    pub query: String,
+    /// row of the last match of the query, where searching goes on from
+    pub last_match: Option<usize>,
+    pub message: String,
+    pub quit: bool,
+}
+
+impl Viewer {
+    pub fn new(doc: Doc, conf: Config, width: usize, height: usize) -> Self {
+        let mut viewer = Self {
+            doc,
+            conf,
+            state: RenderState::default(),
+            lines: Vec::new(),
+            plain_lines: Vec::new(),
+            map: SourceMap::default(),
+            links: Vec::new(),
+            scroll: 0,
+            width,
+            height,
+            mode: Mode::Normal,
+            query: String::new(),
+            last_match: None,
+            message: String::new(),
+            quit: false,
+        };
+        viewer.render();
+        viewer
+    }
+
+    /// Render the document again, for the current width and state.
+    pub fn render(&mut self) {
+        self.conf.width = self.width;
+        let (output, map) = doc_to_ansi_string_with_state(&self.doc, &self.conf, self.state.clone());
+        self.lines = output.split('\n').map(|line| line.to_string()).collect();
+        self.map = map;
+        self.update_links();
+        self.scroll = self.scroll.min(self.max_scroll());
+        self.last_match = None;
+    }
+
+    /// Take what can be focused from the lines and the source map, after they changed.
+    fn update_links(&mut self) {
+        self.plain_lines = self.lines.iter().map(|line| strip_ansi(line)).collect();
+        let spoilers = self.conf.spoiler.treatment == SpoilerTreatment::Focus
+            && !self.conf.spoiler.reveal;
+        self.links = self.map.spans.iter()
+            .filter(|span| {
+                matches!(span.kind, NodeKind::Link | NodeKind::NavLink)
+                    || spoilers && span.kind == NodeKind::Spoiler
+            })
+            .cloned()
+            .collect();
+        self.links.sort();
+        if let Some(focused) = &self.state.focused
+            && !self.links.iter().any(|span| &span.path == focused)
+        {
+            self.state.focused = None;
+        }
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+        let top = self.top_node();
+        self.width = width;
+        self.height = height;
+        self.render();
+        if let Some(path) = top {
+            self.scroll_to_node(&path);
+        }
+    }
+
+    /// Rows available for the document: everything but the status line.
+    pub fn page_height(&self) -> usize {
+        self.height.max(2) - 1
+    }
+
+    pub fn max_scroll(&self) -> usize {
+        self.lines.len().saturating_sub(self.page_height())
+    }
+
+    pub fn handle(&mut self, key: Key) {
+        self.message.clear();
+        if let Mode::Search(query) = &mut self.mode {
+            match key {
+                Key::Char(ch) => query.push(ch),
+                Key::Backspace => { query.pop(); },
+                Key::Esc | Key::Interrupt => self.mode = Mode::Normal,
+                Key::Enter => {
+                    self.query = mem::take(query);
+                    self.mode = Mode::Normal;
+                    self.last_match = None;
+                    self.search(true, false);
+                },
+                _ => { },
+            }
+            return;
+        }
+        let page = self.page_height();
+        match key {
+            Key::Char('q') | Key::Esc | Key::Interrupt => self.quit = true,
+            Key::Up | Key::Char('k') => self.scroll_by(-1),
+            Key::Down | Key::Char('j') => self.scroll_by(1),
+            Key::PageUp | Key::Char('b') => self.scroll_by(-(page as isize)),
+            Key::PageDown | Key::Char(' ') => self.scroll_by(page as isize),
+            Key::Home | Key::Char('g') => self.scroll = 0,
+            Key::End | Key::Char('G') => self.scroll = self.max_scroll(),
+            Key::Char('/') => self.mode = Mode::Search(String::new()),
+            Key::Char('n') => self.search(true, true),
+            Key::Char('N') => self.search(false, true),
+            Key::Tab => self.focus_link(true),
+            Key::BackTab => self.focus_link(false),
+            Key::Enter => self.follow_link(),
+            Key::Char('c') => self.toggle_section(),
+            Key::Char('x') => self.check_item(),
+            _ => { },
+        }
+    }
+
+    pub fn scroll_by(&mut self, amount: isize) {
+        self.scroll = self.scroll.saturating_add_signed(amount).min(self.max_scroll());
+    }
+
+    /// Scroll so the given row is visible, preferably near the top of the page.
+    pub fn scroll_to_row(&mut self, row: usize) {
+        if row < self.scroll || row >= self.scroll + self.page_height() {
+            self.scroll = row.min(self.max_scroll());
+        }
+    }
+
+    fn scroll_to_node(&mut self, path: &[usize]) {
+        if let Some(span) = self.map.spans_of(path).next() {
+            self.scroll = span.start.0.min(self.max_scroll());
+        }
+    }
+
+    /// Innermost node at the start of the first visible line.
+    fn top_node(&self) -> Option<NodePath> {
+        let line = self.plain_lines.get(self.scroll)?;
+        let col = line.len() - line.trim_start().len();
+        self.map.node_at(self.scroll, col).map(|span| span.path.clone())
+    }
+
+    /// Scroll to the next or previous line that contains the query, from the last match while it
+    /// is on the page and else from the top of the page.
+    pub fn search(&mut self, forward: bool, skip_current: bool) {
+        if self.query.is_empty() {
+            self.message = "no search query".to_string();
+            return;
+        }
+        let query = self.query.to_lowercase();
+        let matches = |row: &usize| self.plain_lines[*row].to_lowercase().contains(&query);
+        let start = self.last_match
+            .filter(|row| (self.scroll..self.scroll + self.page_height()).contains(row))
+            .unwrap_or(self.scroll);
+        let found = if forward {
+            let from = start + usize::from(skip_current);
+            (from..self.plain_lines.len()).find(matches)
+        } else {
+            (0..start).rev().find(matches)
+        };
+        match found {
+            Some(row) => {
+                self.scroll = row.min(self.max_scroll());
+                self.last_match = Some(row);
+                // on the last page the match isn't at the top, so tell where it is
+                if row > self.scroll {
+                    self.message = format!("match on line {}", row + 1);
+                }
+            },
+            None => self.message = format!("pattern not found: {}", self.query),
+        }
+    }
+
+    pub fn focus_link(&mut self, forward: bool) {
+        if self.links.is_empty() {
+            self.message = "no links".to_string();
+            return;
+        }
+        let current = self.state.focused.as_ref()
+            .and_then(|path| self.links.iter().position(|span| &span.path == path));
+        let visible = |span: &&Span| span.start.0 >= self.scroll;
+        let index = match (current, forward) {
+            (Some(i), true) => (i + 1) % self.links.len(),
+            (Some(i), false) => (i + self.links.len() - 1) % self.links.len(),
+            (None, true) => self.links.iter().position(|span| visible(&span)).unwrap_or(0),
+            (None, false) => self.links.iter().rposition(|span| visible(&span))
+                .unwrap_or(self.links.len() - 1),
+        };
+        let span = self.links[index].clone();
+        self.state.focused = Some(span.path);
+        self.render();
+        self.scroll_to_row(span.start.0);
+        self.scroll_to_row(span.end.0);
+    }
+
+    pub fn follow_link(&mut self) {
+        let Some(path) = self.state.focused.clone() else {
+            self.message = "no link focused, use tab".to_string();
+            return;
+        };
+        let url = match node_at_path(&self.doc, &path) {
+            Some(Node::Link(link)) | Some(Node::Item(ParagraphItem::Link(link))) => link.url.clone(),
+            _ => return,
+        };
+        if !url.starts_with('#') {
+            self.message = format!("external link: {url}");
+            return;
+        }
+        let Some(target) = find_section(&self.doc, &url) else {
+            self.message = format!("link target not found: {url}");
+            return;
+        };
+        let before = self.state.collapsed.len();
+        self.state.collapsed.retain(|collapsed| !target.starts_with(collapsed));
+        if self.state.collapsed.len() != before {
+            self.render();
+        }
+        self.scroll_to_node(&target);
+    }
+
+    /// Collapse or expand the section of which the heading is last seen at the top of the page.
+    pub fn toggle_section(&mut self) {
+        let heading = self.map.spans.iter()
+            .filter(|span| span.kind == NodeKind::Heading && span.start.0 <= self.scroll)
+            .max_by_key(|span| (span.start, span.path.len()));
+        let Some(heading) = heading else {
+            self.message = "no section".to_string();
+            return;
+        };
+        let path = heading.path.clone();
+        if !self.state.collapsed.remove(&path) {
+            self.state.collapsed.insert(path.clone());
+        }
+        self.render();
+        self.scroll_to_node(&path);
+    }
+
+    /// Check or uncheck the checked list item of the focused link, or else the first one on the
+    /// page.
+    pub fn check_item(&mut self) {
+        let focused = self.state.focused.as_ref()
+            .and_then(|path| self.map.spans_of(path).next())
+            .map(|span| span.start.0);
+        let item = focused.into_iter()
+            .chain(self.scroll..self.scroll + self.page_height())
+            .find_map(|row| checklist::checklist_item_at(&self.doc, &self.map, row))
+            .cloned();
+        let Some(item) = item else {
+            self.message = "no checklist item".to_string();
+            return;
+        };
+        if checklist::toggle_checked(&mut self.doc, &item).is_none() {
+            return;
+        }
+        let list = &item[..item.len() - 1];
+        let (doc, conf, state) = (&self.doc, &self.conf, &self.state);
+        if checklist::rerender_checklist(doc, conf, state, list, &mut self.lines, &mut self.map) {
+            self.update_links();
+        } else {
+            self.render();
+        }
+    }
+
+    /// Lines to show on the terminal: exactly `height` lines, the last one being the status line.
+    pub fn draw(&self) -> Vec<String> {
+        let mut screen = Vec::with_capacity(self.height);
+        for row in self.scroll..self.scroll + self.page_height() {
+            let mut line = self.lines.get(row).cloned().unwrap_or_default();
+            line += RESET;
+            screen.push(line);
+        }
+        let status = match &self.mode {
+            Mode::Search(query) => format!("/{query}"),
+            Mode::Normal if !self.message.is_empty() => self.message.clone(),
+            Mode::Normal => format!(
+                "lines {}-{} of {}",
+                self.scroll + 1,
+                (self.scroll + self.page_height()).min(self.lines.len()),
+                self.lines.len(),
+            ),
+        };
+        let status: String = status.chars().take(self.width).collect();
+        screen.push(format!("{EFFECT7}{status:<width$}{RESET}", width = self.width));
+        screen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{ Format, parse_input };
+
+    /// Twenty lines that say which line they are, with a needle on some of them.
+    fn doc(needles: &[usize]) -> Doc {
+        let md: Vec<String> = (1..=20)
+            .map(|i| format!("line {i}{}", if needles.contains(&i) { " needle" } else { "" }))
+            .collect();
+        parse_input(&md.join("\n\n"), Format::Markdown).expect("markdown always parses")
+    }
+
+    fn conf() -> Config {
+        let mut conf = Config::compact();
+        conf.header.window_title = false;
+        conf
+    }
+
+    fn keys(text: &str) -> Vec<Key> {
+        parse_keys(text)
+    }
+
+    fn view(needles: &[usize], script: &str) -> (Viewer, Headless) {
+        let mut backend = Headless::new(40, 6, keys(script));
+        let viewer = run(doc(needles), conf(), &mut backend).expect("script ends with quitting");
+        (viewer, backend)
+    }
+
+    fn top_line(backend: &Headless) -> String {
+        backend.text()[0].trim().to_string()
+    }
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(
+            parse_keys("\x1B[Aj\x03\x1B[6~\x1B\r"),
+            [Key::Up, Key::Char('j'), Key::Interrupt, Key::PageDown, Key::Esc, Key::Enter],
+        );
+    }
+
+    #[test]
+    fn scrolls_and_quits() {
+        let (viewer, backend) = view(&[], "jjjq");
+        assert!(viewer.quit);
+        assert_eq!(backend.screen.len(), 6);
+        assert_eq!(viewer.scroll, 3);
+        assert!(backend.text()[5].starts_with("lines 4-8 of"), "{:?}", backend.text());
+    }
+
+    #[test]
+    fn interrupt_cancels_search() {
+        let (viewer, backend) = view(&[15], "/needle\x03q");
+        assert_eq!(viewer.mode, Mode::Normal);
+        assert_eq!(viewer.scroll, 0);
+        assert!(backend.text()[5].starts_with("lines 1-"));
+
+        let mut backend = Headless::new(40, 6, keys("/nee\x03\x03"));
+        let viewer = run(doc(&[15]), conf(), &mut backend).expect("second interrupt quits");
+        assert!(viewer.quit);
+    }
+
+    fn row_of(viewer: &Viewer, line: &str) -> usize {
+        viewer.plain_lines.iter().position(|plain| plain == line).expect("line is rendered")
+    }
+
+    #[test]
+    fn searches_forward_and_back() {
+        let (viewer, backend) = view(&[3, 7], "/needle\rnNq");
+        assert_eq!(viewer.last_match, Some(row_of(&viewer, "line 3 needle")));
+        assert_eq!(top_line(&backend), "line 3 needle");
+    }
+
+    #[test]
+    fn next_match_on_the_last_page() {
+        let needles = [18, 19, 20];
+        let (viewer, _) = view(&needles, "/needle\rq");
+        let rows: Vec<usize> = needles.iter()
+            .map(|i| row_of(&viewer, &format!("line {i} needle")))
+            .collect();
+        assert_eq!(viewer.last_match, Some(rows[0]));
+        let mut viewer = Viewer::new(doc(&needles), conf(), 40, 6);
+        keys("/needle\rn").into_iter().for_each(|key| viewer.handle(key));
+        assert_eq!(viewer.last_match, Some(rows[1]));
+        let status = strip_ansi(&viewer.draw()[5]);
+        assert_eq!(status.trim_end(), format!("match on line {}", rows[1] + 1));
+        let (viewer, _) = view(&needles, "/needle\rnnq");
+        assert_eq!(viewer.last_match, Some(rows[2]));
+        let (viewer, _) = view(&needles, "/needle\rnnNq");
+        assert_eq!(viewer.last_match, Some(rows[1]));
+    }
+
+    #[test]
+    fn checks_items() {
+        let md = "- [ ] first task\n- [x] second task";
+        let tasks = parse_input(md, Format::Markdown).expect("markdown always parses");
+        let mut backend = Headless::new(40, 6, keys("xq"));
+        let viewer = run(tasks.clone(), conf(), &mut backend).expect("script ends with quitting");
+        assert!(backend.text()[0].contains("☑ first task"), "{:?}", backend.text());
+        assert_eq!(viewer.lines.len(), Viewer::new(viewer.doc.clone(), conf(), 40, 6).lines.len());
+
+        let mut backend = Headless::new(40, 6, keys("xxq"));
+        run(tasks, conf(), &mut backend).expect("script ends with quitting");
+        assert_eq!(backend.text()[..2], ["☐ first task", "☑ second task"]);
+
+        let mut viewer = Viewer::new(doc(&[]), conf(), 40, 6);
+        viewer.handle(Key::Char('x'));
+        assert_eq!(viewer.message, "no checklist item");
+    }
+
+    #[test]
+    fn decodes_keys_cut_off_between_reads() {
+        let mut decoder = KeyDecoder::default();
+        assert_eq!(decoder.decode(b"j\x1B["), [Key::Char('j')]);
+        assert_eq!(decoder.decode(b"6"), []);
+        assert_eq!(decoder.decode(b"~\xC3"), [Key::PageDown]);
+        assert_eq!(decoder.decode(b"\xA9\x1B[A"), [Key::Char('é'), Key::Up]);
+        assert_eq!(decoder.decode("日本".as_bytes().split_at(4).0), [Key::Char('日')]);
+        assert_eq!(decoder.decode("日本".as_bytes().split_at(4).1), [Key::Char('本')]);
+        // escape on its own, or followed by something that is no sequence
+        assert_eq!(decoder.decode(b"\x1B"), []);
+        assert_eq!(decoder.flush(), [Key::Esc]);
+        assert_eq!(decoder.decode(b"\x1Bx"), [Key::Esc, Key::Char('x')]);
+        assert_eq!(decoder.decode(b"\xFFa"), [Key::Char('\u{FFFD}'), Key::Char('a')]);
+        assert_eq!(decoder.flush(), []);
+    }
+
+    #[test]
+    fn keys_cut_off_between_reads_reach_the_viewer() {
+        let mut decoder = KeyDecoder::default();
+        let keys: Vec<Key> = [&b"\x1B[6"[..], b"~j\x1B", b"[B/\xC3", b"\xA9"].iter()
+            .flat_map(|bytes| decoder.decode(bytes))
+            .collect();
+        let mut backend = Headless::new(40, 6, keys);
+        let err = run(doc(&[]), conf(), &mut backend).expect_err("the keys run out");
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(backend.text()[5].starts_with("/é"), "{:?}", backend.text());
+        // a page of five lines, and two more
+        assert_eq!(top_line(&backend), "line 8");
+    }
+}